@@ -0,0 +1,64 @@
+use super::{neighbour_mask, place_end, random_direction, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{Direction, Maze, Point};
+use rand::SeedableRng;
+
+/// Binary tree algorithm.
+///
+/// Every cell opens either its top or its left wall. It needs no memory at all,
+/// but the top row and the left column always end up as long straight corridors.
+pub struct BinaryTree {
+	/// The next cell to open a wall of, `None` once they all have.
+	next: Option<Point>,
+	seed: u64,
+	rng: MazeRng,
+}
+impl BinaryTree {
+	pub fn new() -> BinaryTree {
+		BinaryTree::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> BinaryTree {
+		BinaryTree {
+			next: None,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+}
+
+impl MazeAlgorithm for BinaryTree {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::BinaryTree);
+		self.next = Some(Point { x: 0, y: 0 });
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		let allowed = 1 << Direction::Top as u8 | 1 << Direction::Left as u8;
+		let dimensions = maze.dimensions;
+		while let Some(location) = self.next {
+			self.next = dimensions.after(location);
+			let mask = neighbour_mask(dimensions, location, |_| true) & allowed;
+			if mask == 0 {
+				// The top left corner is the root of the tree
+				continue;
+			}
+			let direction = random_direction(&mut self.rng, mask);
+			maze.break_wall(location, direction);
+			return Some(Step { location, direction });
+		}
+		place_end(maze);
+		None
+	}
+}
+
+impl Default for BinaryTree {
+	fn default() -> BinaryTree {
+		BinaryTree::new()
+	}
+}
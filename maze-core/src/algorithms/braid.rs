@@ -0,0 +1,108 @@
+use super::{neighbour_mask, place_end, random_direction, MazeRng};
+use crate::maze::{Direction, Maze, Point};
+use rand::Rng;
+
+/// Stream of the braiding generator, so it never repeats the numbers the maze was carved with.
+const BRAID_STREAM: u64 = 0xb4a1d;
+
+/// Directions the ball can leave `location` in.
+fn open_mask(maze: &Maze, location: Point) -> u8 {
+	let mut mask = 0;
+	for &dir in &Direction::ALL {
+		if maze.is_open(location, dir) {
+			mask |= 1 << dir as u8;
+		}
+	}
+	mask
+}
+
+fn is_dead_end(maze: &Maze, location: Point) -> bool {
+	open_mask(maze, location).count_ones() == 1
+}
+
+/// Opens up about `percent` of the dead ends of a generated maze, adding loops to it.
+///
+/// Each dead end picked gets one more of its walls broken, preferring walls towards
+/// other dead ends so that one break gets rid of two of them. The dead ends are
+/// drawn from the maze's own seed, so a braided maze is as reproducible as the
+/// perfect one. The exit is moved afterwards, since the shortest ways changed.
+pub fn braid(maze: &mut Maze, percent: u8) {
+	if percent == 0 {
+		return;
+	}
+	let mut rng = MazeRng::new(maze.seed, BRAID_STREAM);
+	for location in maze.dimensions.points() {
+		// Breaking a wall next door may have opened this one already
+		if !is_dead_end(maze, location) || rng.gen_range(0, 100) >= percent {
+			continue;
+		}
+		let closed = neighbour_mask(maze.dimensions, location, |_| true) & !open_mask(maze, location);
+		let mut dead_ends = 0;
+		for &dir in &Direction::ALL {
+			if closed & (1 << dir as u8) != 0 && is_dead_end(maze, location.dir(dir)) {
+				dead_ends |= 1 << dir as u8;
+			}
+		}
+		let candidates = if dead_ends != 0 { dead_ends } else { closed };
+		maze.break_wall(location, random_direction(&mut rng, candidates));
+	}
+	place_end(maze);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algorithms::{Algorithm, Generator, MazeAlgorithm};
+	use crate::maze::Dimensions;
+
+	fn dead_ends(maze: &Maze) -> usize {
+		maze.dimensions.points().filter(|&p| is_dead_end(maze, p)).count()
+	}
+
+	fn generated(dimensions: Dimensions) -> Maze {
+		let mut maze = Maze::with_dimensions(dimensions);
+		Generator::with_seed(Algorithm::Backtracker, 99).generate(&mut maze, || {});
+		maze
+	}
+
+	#[test]
+	fn full_braid_leaves_no_dead_ends() {
+		for &dimensions in &[Dimensions::SMALL, Dimensions::LARGE] {
+			let mut maze = generated(dimensions);
+			assert!(dead_ends(&maze) > 0);
+			braid(&mut maze, 100);
+			assert_eq!(dead_ends(&maze), 0);
+			assert_eq!(maze.reachable_from(maze.start), dimensions.cells());
+		}
+	}
+
+	#[test]
+	fn partial_braid_removes_some_dead_ends() {
+		let mut maze = generated(Dimensions::LARGE);
+		let before = dead_ends(&maze);
+		braid(&mut maze, 50);
+		let after = dead_ends(&maze);
+		assert!(after > 0 && after < before, "{} -> {}", before, after);
+	}
+
+	#[test]
+	fn no_braid_keeps_the_maze_perfect() {
+		let mut maze = generated(Dimensions::MEDIUM);
+		let end = maze.end;
+		braid(&mut maze, 0);
+		assert_eq!(dead_ends(&maze), dead_ends(&generated(Dimensions::MEDIUM)));
+		assert_eq!(maze.end, end);
+	}
+
+	#[test]
+	fn braiding_is_reproducible() {
+		let mut a = generated(Dimensions::MEDIUM);
+		let mut b = generated(Dimensions::MEDIUM);
+		braid(&mut a, 40);
+		braid(&mut b, 40);
+		assert!(Dimensions::MEDIUM.points().all(|p| {
+			a.bitmap_left.get(p) == b.bitmap_left.get(p) && a.bitmap_top.get(p) == b.bitmap_top.get(p)
+		}));
+		assert_eq!(a.end, b.end);
+	}
+}
@@ -0,0 +1,169 @@
+use super::{place_end, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{Direction, Maze, Point, MAX_WIDTH};
+use rand::{Rng, SeedableRng};
+
+/// Marks a cell of the current row that isn't in any set yet.
+const NO_SET: u8 = 0;
+
+/// Where `step` carries on from in the current row.
+#[derive(Copy, Clone)]
+enum Phase {
+	/// Joining the neighbours from column `x` on.
+	Join { x: u8 },
+	/// Looking for the next set to continue down, from column `x` on.
+	Down { x: u8 },
+	/// Going through the cells of the set first met on column `x`, from `column` on.
+	/// `member` of them were passed, and the one numbered `required` has to go down.
+	Member { x: u8, column: u8, required: u8, member: u8 },
+	Done,
+}
+
+/// Eller's algorithm.
+///
+/// Builds the maze one row at a time and only remembers which set every cell of
+/// the current row belongs to, so it needs a single byte per column.
+pub struct Eller {
+	sets: [u8; MAX_WIDTH as usize],
+	/// Sets of the next row, as far as they're known.
+	below: [u8; MAX_WIDTH as usize],
+	next_set: u8,
+	y: u8,
+	phase: Phase,
+	seed: u64,
+	rng: MazeRng,
+}
+impl Eller {
+	pub fn new() -> Eller {
+		Eller::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> Eller {
+		Eller {
+			sets: [NO_SET; MAX_WIDTH as usize],
+			below: [NO_SET; MAX_WIDTH as usize],
+			next_set: NO_SET,
+			y: 0,
+			phase: Phase::Done,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+
+	/// A set id not used anywhere in the row. There are far more ids than columns.
+	fn fresh_set(&mut self) -> u8 {
+		loop {
+			self.next_set = self.next_set.wrapping_add(1);
+			if self.next_set != NO_SET && !self.sets.contains(&self.next_set) {
+				return self.next_set;
+			}
+		}
+	}
+
+	/// Puts the cells of the current row that aren't in a set yet in a new one each.
+	fn fill_row(&mut self, width: u8) {
+		for x in 0..width as usize {
+			if self.sets[x] == NO_SET {
+				self.sets[x] = self.fresh_set();
+			}
+		}
+	}
+
+	fn merge(&mut self, from: u8, into: u8) {
+		for set in self.sets.iter_mut() {
+			if *set == from {
+				*set = into;
+			}
+		}
+	}
+}
+
+impl MazeAlgorithm for Eller {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::Eller);
+		self.sets = [NO_SET; MAX_WIDTH as usize];
+		self.y = 0;
+		self.fill_row(maze.dimensions.width);
+		self.phase = Phase::Join { x: 0 };
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		// Columns past the width are never given a set, so they never take part
+		let width = maze.dimensions.width;
+		let y = self.y;
+		let last_row = y == maze.dimensions.height - 1;
+		loop {
+			match self.phase {
+				// Join neighbours at random. The last row has to join everything left.
+				Phase::Join { x } if x + 1 < width => {
+					self.phase = Phase::Join { x: x + 1 };
+					let (left, right) = (self.sets[x as usize], self.sets[x as usize + 1]);
+					if left != right && (last_row || self.rng.gen()) {
+						maze.break_wall(Point { x, y }, Direction::Right);
+						self.merge(right, left);
+						return Some(Step { location: Point { x, y }, direction: Direction::Right });
+					}
+				}
+				Phase::Join { .. } if last_row => {
+					self.phase = Phase::Done;
+					place_end(maze);
+					return None;
+				}
+				Phase::Join { .. } => {
+					self.below = [NO_SET; MAX_WIDTH as usize];
+					self.phase = Phase::Down { x: 0 };
+				}
+
+				// Every set needs to continue down at least once, or it would be cut off
+				Phase::Down { x } if x < width => {
+					let set = self.sets[x as usize];
+					self.phase = if self.sets[..x as usize].contains(&set) {
+						// Already handled at its first cell
+						Phase::Down { x: x + 1 }
+					} else {
+						// Counted in a u8: sampling a usize would differ between 32 and 64 bit
+						let members = self.sets.iter().filter(|&&s| s == set).count() as u8;
+						let required = self.rng.gen_range(0, members);
+						Phase::Member { x, column: x, required, member: 0 }
+					};
+				}
+				Phase::Down { .. } => {
+					self.sets = self.below;
+					self.y += 1;
+					self.fill_row(width);
+					self.phase = Phase::Join { x: 0 };
+					return self.step(maze);
+				}
+				Phase::Member { x, column, .. } if column >= width => {
+					self.phase = Phase::Down { x: x + 1 };
+				}
+				Phase::Member { x, column, required, member } => {
+					let set = self.sets[x as usize];
+					if self.sets[column as usize] != set {
+						self.phase = Phase::Member { x, column: column + 1, required, member };
+						continue;
+					}
+					self.phase = Phase::Member { x, column: column + 1, required, member: member + 1 };
+					if member == required || self.rng.gen_bool(1.0 / 3.0) {
+						maze.break_wall(Point { x: column, y }, Direction::Bottom);
+						self.below[column as usize] = set;
+						return Some(Step { location: Point { x: column, y }, direction: Direction::Bottom });
+					}
+				}
+
+				Phase::Done => return None,
+			}
+		}
+	}
+}
+
+impl Default for Eller {
+	fn default() -> Eller {
+		Eller::new()
+	}
+}
@@ -0,0 +1,189 @@
+use super::{place_end, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{Dimensions, Direction, Maze, Point, MAX_HEIGHT, MAX_WIDTH};
+use rand::{Rng, SeedableRng};
+
+const CELLS: usize = MAX_WIDTH as usize * MAX_HEIGHT as usize;
+/// Edge indices are shuffled within `0 .. 1 << EDGE_BITS`, which has to cover the largest maze.
+const EDGE_BITS: u32 = 10;
+const _: () = assert!(edges(Dimensions::LARGE) <= 1 << EDGE_BITS);
+const HALF_BITS: u32 = EDGE_BITS / 2;
+const HALF_MASK: u16 = (1 << HALF_BITS) - 1;
+
+/// Randomized Kruskal's algorithm.
+///
+/// Walls are knocked down in random order whenever they separate two cells that
+/// aren't connected yet. Shuffling all ~1000 walls would take 2K of RAM, so the
+/// order comes from a small Feistel network instead, which is a random permutation
+/// of the edge indices that only needs its round keys.
+pub struct Kruskal {
+	/// Union-find forest of the cells, indexed by `y * MAX_WIDTH + x`.
+	parent: [u16; CELLS],
+	/// Round keys of the shuffle, the next value to shuffle and the walls left to break.
+	keys: [u8; 4],
+	next: u16,
+	remaining: u16,
+	seed: u64,
+	rng: MazeRng,
+}
+impl Kruskal {
+	pub fn new() -> Kruskal {
+		Kruskal::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> Kruskal {
+		Kruskal {
+			parent: [0; CELLS],
+			keys: [0; 4],
+			next: 0,
+			remaining: 0,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+
+	fn find(&mut self, mut cell: u16) -> u16 {
+		while self.parent[cell as usize] != cell {
+			// Path halving keeps the trees flat without needing ranks
+			let grandparent = self.parent[self.parent[cell as usize] as usize];
+			self.parent[cell as usize] = grandparent;
+			cell = grandparent;
+		}
+		cell
+	}
+
+	/// Joins the sets of `a` and `b`. Returns false if they already were the same set.
+	fn union(&mut self, a: u16, b: u16) -> bool {
+		let root_a = self.find(a);
+		let root_b = self.find(b);
+		if root_a == root_b {
+			return false;
+		}
+		self.parent[root_b as usize] = root_a;
+		true
+	}
+}
+
+fn index(location: Point) -> u16 {
+	location.y as u16 * MAX_WIDTH as u16 + location.x as u16
+}
+
+fn horizontal_edges(dimensions: Dimensions) -> u16 {
+	(dimensions.width as u16 - 1) * dimensions.height as u16
+}
+
+/// Number of inner walls: the horizontal edges are numbered first, then the vertical ones.
+const fn edges(dimensions: Dimensions) -> u16 {
+	let width = dimensions.width as u16;
+	let height = dimensions.height as u16;
+	(width - 1) * height + width * (height - 1)
+}
+
+/// The cell on the top/left side of an edge, and the direction the edge is in.
+fn edge(dimensions: Dimensions, edge: u16) -> (Point, Direction) {
+	let horizontal = horizontal_edges(dimensions);
+	if edge < horizontal {
+		let row_length = dimensions.width as u16 - 1;
+		(Point { x: (edge % row_length) as u8, y: (edge / row_length) as u8 }, Direction::Right)
+	} else {
+		let edge = edge - horizontal;
+		let row_length = dimensions.width as u16;
+		(Point { x: (edge % row_length) as u8, y: (edge / row_length) as u8 }, Direction::Bottom)
+	}
+}
+
+/// A bijection on `0 .. 1 << EDGE_BITS` picked by `keys`.
+fn permute(value: u16, keys: &[u8; 4]) -> u16 {
+	let mut left = value >> HALF_BITS;
+	let mut right = value & HALF_MASK;
+	for &key in keys {
+		let mixed = (right.wrapping_mul(0x9d) ^ key as u16).wrapping_add(right >> 2) & HALF_MASK;
+		let next = left ^ mixed;
+		left = right;
+		right = next;
+	}
+	(left << HALF_BITS) | right
+}
+
+impl MazeAlgorithm for Kruskal {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::Kruskal);
+		for (cell, parent) in self.parent.iter_mut().enumerate() {
+			*parent = cell as u16;
+		}
+		self.keys = self.rng.gen();
+		self.next = 0;
+		self.remaining = maze.dimensions.cells() - 1;
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		let dimensions = maze.dimensions;
+		while self.remaining > 0 && self.next < 1 << EDGE_BITS {
+			let shuffled = permute(self.next, &self.keys);
+			self.next += 1;
+			if shuffled >= edges(dimensions) {
+				// Outside of the grid, skipping them keeps the rest a permutation
+				continue;
+			}
+			let (location, direction) = edge(dimensions, shuffled);
+			if self.union(index(location), index(location.dir(direction))) {
+				maze.break_wall(location, direction);
+				self.remaining -= 1;
+				return Some(Step { location, direction });
+			}
+		}
+		place_end(maze);
+		None
+	}
+}
+
+impl Default for Kruskal {
+	fn default() -> Kruskal {
+		Kruskal::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn last_edge_is_in_the_corner() {
+		for &dimensions in &[Dimensions::SMALL, Dimensions::LARGE] {
+			let (location, dir) = edge(dimensions, edges(dimensions) - 1);
+			let corner = Point { x: dimensions.width - 1, y: dimensions.height - 1 };
+			assert_eq!(dimensions.neighbour(location, dir), Some(corner));
+			let (location, dir) = edge(dimensions, horizontal_edges(dimensions) - 1);
+			assert_eq!(dimensions.neighbour(location, dir), Some(corner));
+		}
+	}
+
+	#[test]
+	fn permutation_is_a_bijection() {
+		let keys = [3, 141, 59, 26];
+		let mut seen = [false; 1 << EDGE_BITS];
+		for value in 0..(1_u16 << EDGE_BITS) {
+			let shuffled = permute(value, &keys) as usize;
+			assert!(!seen[shuffled]);
+			seen[shuffled] = true;
+		}
+	}
+
+	#[test]
+	fn union_find_tracks_sets() {
+		let mut kruskal = Kruskal::new();
+		for (cell, parent) in kruskal.parent.iter_mut().enumerate() {
+			*parent = cell as u16;
+		}
+		assert!(kruskal.union(1, 2));
+		assert!(kruskal.union(3, 2));
+		assert!(!kruskal.union(1, 3));
+		assert_eq!(kruskal.find(1), kruskal.find(3));
+		assert_ne!(kruskal.find(1), kruskal.find(4));
+	}
+}
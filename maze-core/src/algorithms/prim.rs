@@ -0,0 +1,97 @@
+use super::{neighbour_mask, place_end, random_direction, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{BitMap, Dimensions, Direction, Maze, Point};
+use rand::{Rng, SeedableRng};
+
+/// Randomized Prim's algorithm.
+///
+/// The maze grows from `start` by connecting a random cell on its frontier each
+/// time, which gives lots of short dead ends. The frontier is kept as a bitmap and
+/// the random cell is found by counting through it, trading speed for memory.
+pub struct Prim {
+	in_maze: BitMap,
+	frontier: BitMap,
+	frontier_size: u16,
+	seed: u64,
+	rng: MazeRng,
+}
+impl Prim {
+	pub fn new() -> Prim {
+		Prim::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> Prim {
+		Prim {
+			in_maze: BitMap::new(false),
+			frontier: BitMap::new(false),
+			frontier_size: 0,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+
+	fn add(&mut self, dimensions: Dimensions, location: Point) {
+		self.in_maze.set(location, true);
+		let in_maze = &self.in_maze;
+		let frontier = &self.frontier;
+		let mask = neighbour_mask(dimensions, location, |p| !in_maze.get(p) && !frontier.get(p));
+		for bit in 0..4 {
+			if mask & (1 << bit) != 0 {
+				self.frontier.set(location.dir(Direction::from_bits(bit)), true);
+				self.frontier_size += 1;
+			}
+		}
+	}
+
+	fn take_random_frontier(&mut self, dimensions: Dimensions) -> Point {
+		let mut index = self.rng.gen_range(0, self.frontier_size);
+		for location in dimensions.points() {
+			if !self.frontier.get(location) {
+				continue;
+			}
+			if index == 0 {
+				self.frontier.set(location, false);
+				self.frontier_size -= 1;
+				return location;
+			}
+			index -= 1;
+		}
+		unreachable!("frontier_size out of sync with the frontier")
+	}
+}
+
+impl MazeAlgorithm for Prim {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::Prim);
+		self.in_maze.set_all(false);
+		self.frontier.set_all(false);
+		self.frontier_size = 0;
+		self.add(maze.dimensions, maze.start);
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		if self.frontier_size == 0 {
+			place_end(maze);
+			return None;
+		}
+		let dimensions = maze.dimensions;
+		let location = self.take_random_frontier(dimensions);
+		let in_maze = &self.in_maze;
+		let mask = neighbour_mask(dimensions, location, |p| in_maze.get(p));
+		let direction = random_direction(&mut self.rng, mask);
+		maze.break_wall(location, direction);
+		self.add(dimensions, location);
+		Some(Step { location, direction })
+	}
+}
+
+impl Default for Prim {
+	fn default() -> Prim {
+		Prim::new()
+	}
+}
@@ -0,0 +1,77 @@
+use super::{place_end, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{Direction, Maze, Point};
+use rand::{Rng, SeedableRng};
+
+/// Sidewinder algorithm.
+///
+/// Goes through each row carving runs of cells to the right, and closes every
+/// run by opening the top wall of one of its cells. Like the binary tree it only
+/// needs to remember where the current run started.
+pub struct Sidewinder {
+	/// The next cell to carve from, `None` once they all have been.
+	next: Option<Point>,
+	/// Column the current run started on.
+	run_start: u8,
+	seed: u64,
+	rng: MazeRng,
+}
+impl Sidewinder {
+	pub fn new() -> Sidewinder {
+		Sidewinder::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> Sidewinder {
+		Sidewinder {
+			next: None,
+			run_start: 0,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+}
+
+impl MazeAlgorithm for Sidewinder {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::Sidewinder);
+		self.next = Some(Point { x: 0, y: 0 });
+		self.run_start = 0;
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		let dimensions = maze.dimensions;
+		while let Some(location) = self.next {
+			self.next = dimensions.after(location);
+			let Point { x, y } = location;
+			let last_column = x == dimensions.width - 1;
+			// The top row has nowhere to go up to, so it is a single run
+			let step = if y > 0 && (last_column || self.rng.gen()) {
+				let up = self.rng.gen_range(self.run_start, x + 1);
+				self.run_start = x + 1;
+				Step { location: Point { x: up, y }, direction: Direction::Top }
+			} else if !last_column {
+				Step { location, direction: Direction::Right }
+			} else {
+				continue;
+			};
+			if last_column {
+				self.run_start = 0;
+			}
+			maze.break_wall(step.location, step.direction);
+			return Some(step);
+		}
+		place_end(maze);
+		None
+	}
+}
+
+impl Default for Sidewinder {
+	fn default() -> Sidewinder {
+		Sidewinder::new()
+	}
+}
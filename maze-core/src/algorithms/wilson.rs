@@ -0,0 +1,102 @@
+use super::{neighbour_mask, place_end, random_direction, Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::maze::{BitMap, Dimensions, DirectionMap, Maze, Point};
+use rand::{Rng, SeedableRng};
+
+/// Wilson's algorithm.
+///
+/// Adds cells with loop-erased random walks, which gives a maze picked uniformly
+/// among all possible ones. The walk only records the last direction taken out of
+/// every cell, so revisiting a cell erases the loop on its own.
+pub struct Wilson {
+	in_maze: BitMap,
+	walk: DirectionMap,
+	/// Where the walk being carved into the maze got to, if one is.
+	carving: Option<Point>,
+	/// Cells not in the maze yet.
+	remaining: u16,
+	seed: u64,
+	rng: MazeRng,
+}
+impl Wilson {
+	pub fn new() -> Wilson {
+		Wilson::with_seed(super::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> Wilson {
+		Wilson {
+			in_maze: BitMap::new(false),
+			walk: DirectionMap::new(),
+			carving: None,
+			remaining: 0,
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+		}
+	}
+
+	fn random_cell_outside(&mut self, dimensions: Dimensions, remaining: u16) -> Point {
+		let mut index = self.rng.gen_range(0, remaining);
+		for location in dimensions.points() {
+			if self.in_maze.get(location) {
+				continue;
+			}
+			if index == 0 {
+				return location;
+			}
+			index -= 1;
+		}
+		unreachable!("fewer cells outside of the maze than remaining")
+	}
+}
+
+impl MazeAlgorithm for Wilson {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = super::begin(maze, self.seed, Algorithm::Wilson);
+		self.in_maze.set_all(false);
+		self.in_maze.set(maze.start, true);
+		self.carving = None;
+		self.remaining = maze.dimensions.cells() - 1;
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		let dimensions = maze.dimensions;
+		loop {
+			// Retrace the walk without its loops, carving it into the maze
+			if let Some(current) = self.carving {
+				if !self.in_maze.get(current) {
+					let direction = self.walk.get(current);
+					self.in_maze.set(current, true);
+					maze.break_wall(current, direction);
+					self.remaining -= 1;
+					self.carving = Some(current.dir(direction));
+					return Some(Step { location: current, direction });
+				}
+				self.carving = None;
+			}
+			if self.remaining == 0 {
+				place_end(maze);
+				return None;
+			}
+
+			let origin = self.random_cell_outside(dimensions, self.remaining);
+			let mut current = origin;
+			while !self.in_maze.get(current) {
+				let dir = random_direction(&mut self.rng, neighbour_mask(dimensions, current, |_| true));
+				self.walk.set(current, dir);
+				current = current.dir(dir);
+			}
+			self.carving = Some(origin);
+		}
+	}
+}
+
+impl Default for Wilson {
+	fn default() -> Wilson {
+		Wilson::new()
+	}
+}
@@ -0,0 +1,210 @@
+use crate::cell::Cell;
+use crate::display::PWM_FREQUENCY;
+use crate::maze::Maze;
+use core::fmt;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+pub type Ball = Point;
+
+impl Ball {
+    pub fn new() -> Ball {
+        Ball {
+            x: 0,
+            y: 0,
+        }
+    }
+    /// The middle of the cell at `p`, for cells `cell_size` pixels wide.
+    pub fn from_point(p: &crate::maze::Point, cell_size: u8) -> Ball {
+        let size = cell_size as u16;
+        Ball {
+            x: (p.x as u16 * size + size / 2) * PWM_FREQUENCY as u16,
+            y: (p.y as u16 * size + size / 2) * PWM_FREQUENCY as u16,
+        }
+    }
+    pub fn to_point(&self, cell_size: u8) -> crate::maze::Point {
+        crate::maze::Point {
+            x: ((self.x / PWM_FREQUENCY as u16) / cell_size as u16) as u8,
+            y: ((self.y / PWM_FREQUENCY as u16) / cell_size as u16) as u8,
+        }
+    }
+
+    /// Moves the ball by `(dx, dy)` in ball coordinates and returns where it ends up.
+    ///
+    /// The ball is kept on the maze, and is stopped at the border of its current
+    /// cell whenever the move would take it through a wall. The x axis is resolved
+    /// before the y axis, so the ball slides along walls instead of sticking to them.
+    pub fn moved(&self, maze: &Maze, dx: i16, dy: i16) -> Ball {
+        let mut newx: i16 = self.x as i16 + dx;
+        let mut newy: i16 = self.y as i16 + dy;
+        if newx < 0 { newx = 0; }
+        if newy < 0 { newy = 0; }
+        if newx >= maze.ball_width() as i16 {
+            newx = maze.ball_width() as i16 - 1;
+        }
+        if newy >= maze.ball_height() as i16 {
+            newy = maze.ball_height() as i16 - 1;
+        }
+        let cell_size = maze.cell_size();
+
+        // move on the x direction first
+        let mut ball_after_screen_pos: Ball = Ball { x: newx as u16, y: self.y };
+
+        let cell = Cell::of_ball_point(self, cell_size);
+        if !cell.contains(&ball_after_screen_pos) {
+            // Entered a new cell
+            let new_cell = Cell::of_ball_point(&ball_after_screen_pos, cell_size);
+            if !maze.connected(&cell, &new_cell) {
+                cell.bound_x(&mut ball_after_screen_pos);
+            }
+        }
+
+        ball_after_screen_pos.y = newy as u16;
+
+        if !cell.contains(&ball_after_screen_pos) {
+            // Entered a new cell
+            let new_cell = Cell::of_ball_point(&ball_after_screen_pos, cell_size);
+            if !maze.connected(&cell, &new_cell) {
+                cell.bound_y(&mut ball_after_screen_pos);
+            }
+        }
+        ball_after_screen_pos
+    }
+}
+
+impl Default for Ball {
+    fn default() -> Ball {
+        Ball::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::Direction;
+
+    fn point(x: u8, y: u8) -> crate::maze::Point {
+        crate::maze::Point { x, y }
+    }
+
+    #[test]
+    fn point_round_trip() {
+        for &cell_size in &[4, 8, 16, 21] {
+            let p = point(5, 2);
+            assert!(Ball::from_point(&p, cell_size).to_point(cell_size) == p);
+            let p = point(0, 0);
+            assert!(Ball::from_point(&p, cell_size).to_point(cell_size) == p);
+        }
+        let p = point(31, 15);
+        assert!(Ball::from_point(&p, 4).to_point(4) == p);
+    }
+
+    #[test]
+    fn ball_starts_in_the_middle_of_the_cell() {
+        let ball = Ball::from_point(&point(1, 0), 16);
+        assert_eq!(ball, Ball { x: 24 * PWM_FREQUENCY as u16, y: 8 * PWM_FREQUENCY as u16 });
+        assert!(Cell::of_point(&point(1, 0), 16) == Cell::of_ball_point(&ball, 16));
+    }
+
+    #[test]
+    fn small_moves_stay_put_in_the_cell() {
+        let maze = Maze::new();
+        let size = maze.cell_size();
+        let ball = Ball::from_point(&point(3, 3), size);
+        let moved = ball.moved(&maze, 5, -5);
+        assert_eq!(moved, Ball { x: ball.x + 5, y: ball.y - 5 });
+    }
+
+    #[test]
+    fn walls_clamp_to_the_cell_border() {
+        let maze = Maze::new();
+        let size = maze.cell_size();
+        let ball = Ball::from_point(&point(3, 3), size);
+        let cell = Cell::of_ball_point(&ball, size);
+
+        let right = ball.moved(&maze, 1000, 0);
+        assert!(cell.contains(&right));
+        assert!(!cell.contains(&Ball { x: right.x + 1, y: right.y }));
+
+        let left = ball.moved(&maze, -1000, 0);
+        assert_eq!(left.x, cell.position.x);
+
+        let up = ball.moved(&maze, 0, -1000);
+        assert_eq!(up.y, cell.position.y);
+
+        let down = ball.moved(&maze, 0, 1000);
+        assert!(cell.contains(&down));
+        assert!(!cell.contains(&Ball { x: down.x, y: down.y + 1 }));
+    }
+
+    #[test]
+    fn open_walls_let_the_ball_through() {
+        let mut maze = Maze::new();
+        let size = maze.cell_size();
+        maze.break_wall(point(3, 3), Direction::Right);
+        let ball = Ball::from_point(&point(3, 3), size);
+
+        let moved = ball.moved(&maze, size as i16 * PWM_FREQUENCY as i16, 0);
+        assert!(moved.to_point(size) == point(4, 3));
+        // The bottom wall is still there
+        let moved = ball.moved(&maze, 0, size as i16 * PWM_FREQUENCY as i16);
+        assert!(moved.to_point(size) == point(3, 3));
+    }
+
+    #[test]
+    fn sliding_diagonally_only_moves_along_open_axes() {
+        let mut maze = Maze::new();
+        let size = maze.cell_size();
+        maze.break_wall(point(3, 3), Direction::Right);
+        let ball = Ball::from_point(&point(3, 3), size);
+
+        let moved = ball.moved(&maze, size as i16 * PWM_FREQUENCY as i16, size as i16 * PWM_FREQUENCY as i16);
+        assert!(moved.to_point(size) == point(4, 3));
+    }
+
+    #[test]
+    fn screen_edges_clamp_the_ball() {
+        let mut maze = Maze::new();
+        let size = maze.cell_size();
+        // Without walls around the corner cell, only the screen edge stops the ball
+        maze.bitmap_left.set_all(false);
+        maze.bitmap_top.set_all(false);
+        let ball = Ball::from_point(&point(0, 0), size);
+        assert_eq!(ball.moved(&maze, -1000, -1000), Ball { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn small_mazes_clamp_the_ball_to_the_maze() {
+        use crate::maze::Dimensions;
+        let mut maze = Maze::with_dimensions(Dimensions::new(5, 3).unwrap());
+        maze.bitmap_left.set_all(false);
+        maze.bitmap_top.set_all(false);
+        let ball = Ball::from_point(&point(4, 2), maze.cell_size());
+        let moved = ball.moved(&maze, 10000, 10000);
+        assert_eq!(moved, Ball { x: maze.ball_width() - 1, y: maze.ball_height() - 1 });
+        assert!(moved.to_point(maze.cell_size()) == point(4, 2));
+    }
+
+    #[test]
+    fn walls_scale_with_the_cells() {
+        use crate::maze::Dimensions;
+        let mut maze = Maze::with_dimensions(Dimensions::SMALL);
+        let size = maze.cell_size();
+        maze.break_wall(point(1, 1), Direction::Right);
+        let ball = Ball::from_point(&point(1, 1), size);
+        let moved = ball.moved(&maze, size as i16 * PWM_FREQUENCY as i16, 0);
+        assert!(moved.to_point(size) == point(2, 1));
+        let moved = ball.moved(&maze, 0, size as i16 * PWM_FREQUENCY as i16);
+        assert!(moved.to_point(size) == point(1, 1));
+    }
+}
@@ -0,0 +1,188 @@
+//! Overall brightness of the panel, set by hand or following the room's light.
+//!
+//! The panel has no brightness input of its own, so it is dimmed by cutting
+//! every bit plane short: OE goes low when the plane is latched and the display
+//! timer raises it again after `lit_ticks` of the plane's period. All planes
+//! are cut by the same fraction, so the levels of the palette keep their ratios.
+
+/// Brightest setting, the LEDs stay lit for the whole of each plane.
+pub const MAX_BRIGHTNESS: u8 = 8;
+/// Full scale of the ambient light sensor, a 12 bit ADC reading.
+const AMBIENT_RANGE: u32 = 4096;
+/// How far past the edge of a step the light has to get before the brightness follows.
+const HYSTERESIS: u32 = 64;
+
+/// Ticks out of a `period` long plane that the LEDs stay lit at `brightness`.
+///
+/// The fraction goes with the square of the brightness, so the steps look
+/// about even. `brightness` is taken between 1 and `MAX_BRIGHTNESS`, the panel
+/// is never switched off completely.
+pub fn lit_ticks(brightness: u8, period: u32) -> u32 {
+    let brightness = brightness.clamp(1, MAX_BRIGHTNESS) as u32;
+    let max = MAX_BRIGHTNESS as u32;
+    // At least a tick, or the dimmest planes wouldn't light at all
+    (period * brightness * brightness / (max * max)).max(1)
+}
+
+/// Brightness for the light falling on a sensor, from its ADC readings.
+///
+/// The sensor is expected to read higher the more light there is, like a
+/// photoresistor between the supply and the input with a resistor to ground.
+/// Readings are averaged and the brightness only changes once the average is
+/// well into the next step, so it doesn't flicker between two of them.
+pub struct AmbientLight {
+    /// Average of the readings, times 8.
+    average: u32,
+    brightness: u8,
+    primed: bool,
+}
+
+impl AmbientLight {
+    pub const fn new() -> AmbientLight {
+        AmbientLight { average: 0, brightness: MAX_BRIGHTNESS, primed: false }
+    }
+
+    /// Adds a reading, returns the brightness for it.
+    pub fn push(&mut self, reading: u16) -> u8 {
+        let reading = (reading as u32).min(AMBIENT_RANGE - 1);
+        let first_reading = !self.primed;
+        if first_reading {
+            self.average = reading * 8;
+            self.primed = true;
+        } else {
+            self.average = self.average - self.average / 8 + reading;
+        }
+        let light = self.average / 8;
+        let step = AMBIENT_RANGE / MAX_BRIGHTNESS as u32;
+        // Lowest reading of each step
+        let bottom = |brightness: u8| (brightness as u32 - 1) * step;
+        let brightness = (1 + light / step) as u8;
+        if first_reading
+            || (brightness > self.brightness && light >= bottom(brightness) + HYSTERESIS)
+            || (brightness < self.brightness && light + HYSTERESIS < bottom(self.brightness))
+        {
+            self.brightness = brightness;
+        }
+        self.brightness
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+}
+
+impl Default for AmbientLight {
+    fn default() -> AmbientLight {
+        AmbientLight::new()
+    }
+}
+
+/// Brightness the panel is shown at: the setting, dimmed by the room's light if asked to.
+pub struct Dimmer {
+    /// Brightness picked by the player, from 1 to `MAX_BRIGHTNESS`.
+    pub brightness: u8,
+    /// Whether to follow the ambient light sensor, never going above `brightness`.
+    pub automatic: bool,
+    ambient: AmbientLight,
+}
+
+impl Dimmer {
+    pub const fn new() -> Dimmer {
+        Dimmer { brightness: MAX_BRIGHTNESS, automatic: false, ambient: AmbientLight::new() }
+    }
+
+    /// Takes a reading of the ambient light sensor.
+    pub fn sense(&mut self, reading: u16) {
+        self.ambient.push(reading);
+    }
+
+    /// Brightness for the ambient light alone.
+    pub fn ambient(&self) -> u8 {
+        self.ambient.brightness()
+    }
+
+    pub fn level(&self) -> u8 {
+        if self.automatic {
+            self.brightness.min(self.ambient.brightness())
+        } else {
+            self.brightness
+        }
+    }
+}
+
+impl Default for Dimmer {
+    fn default() -> Dimmer {
+        Dimmer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimming_cuts_the_planes_short() {
+        assert_eq!(lit_ticks(MAX_BRIGHTNESS, 1000), 1000);
+        assert_eq!(lit_ticks(MAX_BRIGHTNESS / 2, 1000), 250);
+        assert_eq!(lit_ticks(1, 1000), 15);
+        // Out of range settings are brought back in
+        assert_eq!(lit_ticks(0, 1000), lit_ticks(1, 1000));
+        assert_eq!(lit_ticks(200, 1000), 1000);
+        assert_eq!(lit_ticks(1, 10), 1);
+        let ticks: Vec<u32> = (1 ..= MAX_BRIGHTNESS).map(|brightness| lit_ticks(brightness, 1666)).collect();
+        assert!(ticks.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn ambient_light_picks_a_step() {
+        let mut ambient = AmbientLight::new();
+        assert_eq!(ambient.push(0), 1);
+        let mut ambient = AmbientLight::new();
+        assert_eq!(ambient.push(4095), MAX_BRIGHTNESS);
+        let mut ambient = AmbientLight::new();
+        assert_eq!(ambient.push(1100), 3);
+    }
+
+    #[test]
+    fn ambient_light_is_smoothed() {
+        let mut ambient = AmbientLight::new();
+        ambient.push(0);
+        // A flash doesn't light the panel up at once
+        assert_eq!(ambient.push(4095), 1);
+        for _ in 0 .. 100 {
+            ambient.push(4095);
+        }
+        assert_eq!(ambient.brightness(), MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn ambient_light_doesnt_flicker_on_an_edge() {
+        let mut ambient = AmbientLight::new();
+        // Just below the bottom of the third step
+        assert_eq!(ambient.push(1020), 2);
+        for &reading in [1030, 1040, 1020, 1050, 1010].iter().cycle().take(200) {
+            assert_eq!(ambient.push(reading), 2);
+        }
+        for _ in 0 .. 100 {
+            ambient.push(1200);
+        }
+        assert_eq!(ambient.brightness(), 3);
+        for &reading in [1020, 990, 1000].iter().cycle().take(200) {
+            assert_eq!(ambient.push(reading), 3);
+        }
+    }
+
+    #[test]
+    fn automatic_dimming_stays_below_the_setting() {
+        let mut dimmer = Dimmer::new();
+        dimmer.brightness = 5;
+        dimmer.sense(0);
+        assert_eq!(dimmer.level(), 5);
+        dimmer.automatic = true;
+        assert_eq!(dimmer.level(), 1);
+        let mut dimmer = Dimmer { brightness: 5, automatic: true, ..Dimmer::new() };
+        dimmer.sense(4095);
+        assert_eq!(dimmer.ambient(), MAX_BRIGHTNESS);
+        assert_eq!(dimmer.level(), 5);
+    }
+}
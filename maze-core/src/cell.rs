@@ -0,0 +1,84 @@
+use crate::display::PWM_FREQUENCY;
+use core::fmt;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Size {
+    width: u16,
+    height: u16,
+}
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} x {}", self.width, self.height)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Cell {
+    pub position: crate::ball::Point,
+    size: Size,
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[({}, {}), ({}, {})]", self.position.x, self.position.y, self.position.x + self.size.width, self.position.y + self.size.height)
+    }
+}
+
+impl Cell {
+    pub fn contains(&self, point: &crate::ball::Point) -> bool {
+        if point.x < self.position.x || point.y < self.position.y {
+            return false;
+        }
+        point.x < (self.position.x + self.size.width) && point.y < (self.position.y + self.size.height)
+    }
+
+    /// The inside of the cell at `point`, for cells `cell_size` pixels wide.
+    pub fn of_point(point: &crate::maze::Point, cell_size: u8) -> Cell {
+        let freq = PWM_FREQUENCY as u16;
+        let size = cell_size as u16 * freq;
+        Cell::interior(crate::ball::Point {
+            x: point.x as u16 * size,
+            y: point.y as u16 * size,
+        }, cell_size)
+    }
+
+    /// The inside of the cell the ball is in, for cells `cell_size` pixels wide.
+    pub fn of_ball_point(point: &crate::ball::Point, cell_size: u8) -> Cell {
+        let size = cell_size as u16 * PWM_FREQUENCY as u16;
+        Cell::interior(crate::ball::Point {
+            x: (point.x / size) * size,
+            y: (point.y / size) * size,
+        }, cell_size)
+    }
+
+    /// The first pixel of a cell is taken by its left and top walls.
+    fn interior(corner: crate::ball::Point, cell_size: u8) -> Cell {
+        let freq = PWM_FREQUENCY as u16;
+        Cell {
+            position: crate::ball::Point {
+                x: corner.x + freq,
+                y: corner.y + freq,
+            },
+            size: Size {
+                height: (cell_size as u16 - 1) * freq,
+                width: (cell_size as u16 - 1) * freq,
+            }
+        }
+    }
+
+    pub fn bound_x(&self, point: &mut crate::ball::Point) {
+        if point.x < self.position.x {
+            point.x = self.position.x;
+        } else if point.x > self.position.x + self.size.width-1 {
+            point.x = self.position.x + self.size.width-1;
+        }
+    }
+
+    pub fn bound_y(&self, point: &mut crate::ball::Point) {
+        if point.y < self.position.y {
+            point.y = self.position.y;
+        } else if point.y > self.position.y + self.size.height - 1 {
+            point.y = self.position.y + self.size.height - 1;
+        }
+    }
+}
\ No newline at end of file
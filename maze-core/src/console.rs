@@ -0,0 +1,313 @@
+//! Line-oriented command shell, as typed over the serial port.
+//!
+//! The firmware feeds every received byte into a `LineBuffer` and parses the
+//! line into a `Command` once it's complete. Running the commands is up to the
+//! firmware, since most of them touch the hardware.
+
+use crate::brightness::MAX_BRIGHTNESS;
+use crate::game::Mode;
+use core::fmt;
+use core::str;
+
+/// Longest line accepted, anything typed past it is dropped.
+pub const LINE_LENGTH: usize = 32;
+
+pub const HELP: &str = "commands: seed [n], regen, solve, dump, export, mute [on|off], level [n], brightness [n], autodim [on|off], autofinish [on|off], mode [free|timed|countdown], stats, calibrate, pause, help";
+
+/// Collects bytes until a line is complete.
+pub struct LineBuffer {
+    buffer: [u8; LINE_LENGTH],
+    length: usize,
+    overflowed: bool,
+    /// The line was ended, the next byte starts a new one.
+    complete: bool,
+}
+
+impl LineBuffer {
+    pub const fn new() -> LineBuffer {
+        LineBuffer {
+            buffer: [0; LINE_LENGTH],
+            length: 0,
+            overflowed: false,
+            complete: false,
+        }
+    }
+
+    /// Adds a received byte. Returns true when it ended a line, which is then
+    /// available from `line` until the next call.
+    ///
+    /// Both `\r` and `\n` end a line, so it works whatever the terminal sends,
+    /// and backspace removes the last character.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.complete {
+            self.length = 0;
+            self.overflowed = false;
+            self.complete = false;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                // The empty line between `\r` and `\n` is not worth reporting
+                if self.length == 0 && !self.overflowed {
+                    return false;
+                }
+                self.complete = true;
+                true
+            }
+            0x08 | 0x7f => {
+                self.length = self.length.saturating_sub(1);
+                false
+            }
+            0x20 ..= 0x7e => {
+                if self.length < LINE_LENGTH {
+                    self.buffer[self.length] = byte;
+                    self.length += 1;
+                } else {
+                    self.overflowed = true;
+                }
+                false
+            }
+            // Control characters and anything that isn't ASCII
+            _ => false,
+        }
+    }
+
+    /// The last complete line, or `Err(LineTooLong)` if it didn't fit.
+    pub fn line(&self) -> Result<&str, ParseError> {
+        if self.overflowed {
+            return Err(ParseError::LineTooLong);
+        }
+        // Only printable ASCII is ever stored
+        Ok(str::from_utf8(&self.buffer[.. self.length]).unwrap_or(""))
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> LineBuffer {
+        LineBuffer::new()
+    }
+}
+
+/// Writes `\n` as `\r\n`, which is what serial terminals expect.
+pub struct CrLf<W: fmt::Write>(pub W);
+
+impl<W: fmt::Write> fmt::Write for CrLf<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut lines = s.split('\n');
+        if let Some(first) = lines.next() {
+            self.0.write_str(first)?;
+        }
+        for line in lines {
+            self.0.write_str("\r\n")?;
+            self.0.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Print the seed, or generate the maze of the given one.
+    Seed(Option<u64>),
+    /// New maze at the same level.
+    Regen,
+    /// Print the shortest way from the ball to the exit.
+    Solve,
+    /// Print the maze.
+    Dump,
+    /// Print the maze in its binary format, as hex.
+    Export,
+    /// Toggle the sound, or switch it on or off.
+    Mute(Option<bool>),
+    /// Print the level, or jump to the given one.
+    Level(Option<u8>),
+    /// Print the brightness, or set it from 1 to `MAX_BRIGHTNESS`.
+    Brightness(Option<u8>),
+    /// Toggle dimming with the ambient light, or switch it on or off.
+    AutoDim(Option<bool>),
+    /// Toggle finishing levels on reaching the exit, or switch it on or off.
+    AutoFinish(Option<bool>),
+    /// Print how the time counts, or change it.
+    Mode(Option<Mode>),
+    Stats,
+    /// Take the current joystick position as its centre.
+    Calibrate,
+    /// Pause the game, or resume it.
+    Pause,
+    Help,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    LineTooLong,
+    UnknownCommand,
+    InvalidArgument,
+    TooManyArguments,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ParseError::LineTooLong => "line too long",
+            ParseError::UnknownCommand => "unknown command, try help",
+            ParseError::InvalidArgument => "invalid argument",
+            ParseError::TooManyArguments => "too many arguments",
+        };
+        f.write_str(message)
+    }
+}
+
+/// A decimal number, or hexadecimal with a `0x` prefix.
+fn parse_number(word: &str) -> Result<u64, ParseError> {
+    let parsed = if word.starts_with("0x") || word.starts_with("0X") {
+        u64::from_str_radix(&word[2 ..], 16)
+    } else {
+        word.parse()
+    };
+    parsed.map_err(|_| ParseError::InvalidArgument)
+}
+
+impl Command {
+    /// Parses a line such as `seed 0x1234`. Returns `Ok(None)` for a blank line.
+    pub fn parse(line: &str) -> Result<Option<Command>, ParseError> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let argument = words.next();
+        if words.next().is_some() {
+            return Err(ParseError::TooManyArguments);
+        }
+        let command = match (name, argument) {
+            ("seed", None) => Command::Seed(None),
+            ("seed", Some(seed)) => Command::Seed(Some(parse_number(seed)?)),
+            ("mute", None) => Command::Mute(None),
+            ("mute", Some("on")) => Command::Mute(Some(true)),
+            ("mute", Some("off")) => Command::Mute(Some(false)),
+            ("mute", Some(_)) => return Err(ParseError::InvalidArgument),
+            ("level", None) => Command::Level(None),
+            ("level", Some(level)) => {
+                let level = parse_number(level)?;
+                if level > u8::MAX as u64 {
+                    return Err(ParseError::InvalidArgument);
+                }
+                Command::Level(Some(level as u8))
+            }
+            ("brightness", None) => Command::Brightness(None),
+            ("brightness", Some(brightness)) => {
+                let brightness = parse_number(brightness)?;
+                if brightness == 0 || brightness > MAX_BRIGHTNESS as u64 {
+                    return Err(ParseError::InvalidArgument);
+                }
+                Command::Brightness(Some(brightness as u8))
+            }
+            ("autodim", None) => Command::AutoDim(None),
+            ("autodim", Some("on")) => Command::AutoDim(Some(true)),
+            ("autodim", Some("off")) => Command::AutoDim(Some(false)),
+            ("autodim", Some(_)) => return Err(ParseError::InvalidArgument),
+            ("autofinish", None) => Command::AutoFinish(None),
+            ("autofinish", Some("on")) => Command::AutoFinish(Some(true)),
+            ("autofinish", Some("off")) => Command::AutoFinish(Some(false)),
+            ("autofinish", Some(_)) => return Err(ParseError::InvalidArgument),
+            ("mode", None) => Command::Mode(None),
+            ("mode", Some(mode)) => match Mode::ALL.iter().find(|known| known.name() == mode) {
+                Some(&mode) => Command::Mode(Some(mode)),
+                None => return Err(ParseError::InvalidArgument),
+            },
+            ("regen", None) => Command::Regen,
+            ("solve", None) => Command::Solve,
+            ("dump", None) => Command::Dump,
+            ("export", None) => Command::Export,
+            ("stats", None) => Command::Stats,
+            ("calibrate", None) => Command::Calibrate,
+            ("pause", None) => Command::Pause,
+            ("help", None) => Command::Help,
+            ("regen", _) | ("solve", _) | ("dump", _) | ("export", _) | ("stats", _) | ("calibrate", _) | ("pause", _) | ("help", _) => {
+                return Err(ParseError::TooManyArguments)
+            }
+            _ => return Err(ParseError::UnknownCommand),
+        };
+        Ok(Some(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(input: &[u8]) -> Vec<Result<String, ParseError>> {
+        let mut buffer = LineBuffer::new();
+        let mut lines = Vec::new();
+        for &byte in input {
+            if buffer.push(byte) {
+                lines.push(buffer.line().map(String::from));
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_end_on_either_newline() {
+        assert_eq!(lines(b"seed\r\nregen\rsolve\n"), vec![
+            Ok("seed".into()), Ok("regen".into()), Ok("solve".into()),
+        ]);
+        assert_eq!(lines(b"dump"), vec![]);
+    }
+
+    #[test]
+    fn backspace_and_garbage_are_handled() {
+        assert_eq!(lines(b"sa\x7feed\x08d\x1b\xff\n"), vec![Ok("seed".into())]);
+        assert_eq!(lines(b"\x08\x08x\n"), vec![Ok("x".into())]);
+    }
+
+    #[test]
+    fn long_lines_are_rejected_once() {
+        let mut input = vec![b'a'; LINE_LENGTH + 5];
+        input.extend_from_slice(b"\nstats\n");
+        assert_eq!(lines(&input), vec![Err(ParseError::LineTooLong), Ok("stats".into())]);
+    }
+
+    #[test]
+    fn newlines_become_crlf() {
+        use core::fmt::Write;
+        let mut out = CrLf(String::new());
+        let split = "\nc";
+        write!(out, "a\nb{}\n", split).unwrap();
+        assert_eq!(out.0, "a\r\nb\r\nc\r\n");
+    }
+
+    #[test]
+    fn commands_parse() {
+        assert_eq!(Command::parse("  seed  "), Ok(Some(Command::Seed(None))));
+        assert_eq!(Command::parse("seed 42"), Ok(Some(Command::Seed(Some(42)))));
+        assert_eq!(Command::parse("seed 0xC001"), Ok(Some(Command::Seed(Some(0xc001)))));
+        assert_eq!(Command::parse("mute"), Ok(Some(Command::Mute(None))));
+        assert_eq!(Command::parse("mute off"), Ok(Some(Command::Mute(Some(false)))));
+        assert_eq!(Command::parse("level 3"), Ok(Some(Command::Level(Some(3)))));
+        assert_eq!(Command::parse("calibrate"), Ok(Some(Command::Calibrate)));
+        assert_eq!(Command::parse("brightness"), Ok(Some(Command::Brightness(None))));
+        assert_eq!(Command::parse("brightness 8"), Ok(Some(Command::Brightness(Some(8)))));
+        assert_eq!(Command::parse("autodim on"), Ok(Some(Command::AutoDim(Some(true)))));
+        assert_eq!(Command::parse("autofinish off"), Ok(Some(Command::AutoFinish(Some(false)))));
+        assert_eq!(Command::parse("mode"), Ok(Some(Command::Mode(None))));
+        assert_eq!(Command::parse("mode countdown"), Ok(Some(Command::Mode(Some(Mode::Countdown)))));
+        assert_eq!(Command::parse("export"), Ok(Some(Command::Export)));
+        assert_eq!(Command::parse("pause"), Ok(Some(Command::Pause)));
+        assert_eq!(Command::parse(""), Ok(None));
+    }
+
+    #[test]
+    fn bad_commands_are_reported() {
+        assert_eq!(Command::parse("teleport"), Err(ParseError::UnknownCommand));
+        assert_eq!(Command::parse("seed pizza"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("level 256"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("mute maybe"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("brightness 0"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("brightness 9"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("autodim dark"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("autofinish soon"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("mode fast"), Err(ParseError::InvalidArgument));
+        assert_eq!(Command::parse("regen now"), Err(ParseError::TooManyArguments));
+        assert_eq!(Command::parse("seed 1 2"), Err(ParseError::TooManyArguments));
+    }
+}
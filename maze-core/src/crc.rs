@@ -0,0 +1,37 @@
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xffff) of `bytes`.
+///
+/// Computed bit by bit: a lookup table would cost 512 bytes of flash for
+/// checksums that are only ever taken over a few hundred bytes at a time.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        // The standard check input for CRC catalogues
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        assert_eq!(crc16(&[]), 0xffff);
+    }
+
+    #[test]
+    fn single_bit_flips_are_caught() {
+        let mut data = *b"maze data";
+        let crc = crc16(&data);
+        for bit in 0..data.len() * 8 {
+            data[bit / 8] ^= 1 << (bit % 8);
+            assert_ne!(crc16(&data), crc);
+            data[bit / 8] ^= 1 << (bit % 8);
+        }
+    }
+}
@@ -0,0 +1,579 @@
+//! Decodes what is sent over a `HUBPort` back into the picture on the panel.
+//!
+//! `port` hands out a `HUBPort` whose pins all report to a shared `Decoder`,
+//! which models the chips of the panel described in the README:
+//!
+//! - the ICND2038S column drivers, one chain of shift registers per half of the
+//!   panel, taking a pixel on each falling edge of the clock and copying the
+//!   whole row to their outputs on the rising edge of the latch
+//! - the D5958SSP row drivers, a shift chain with a stage per row, clocked by A
+//!   with C as its data input. The upper half of the panel hangs off its first
+//!   stages and the lower half off the others, so one bit shifted in every
+//!   `ROWS` rows scans both halves at once.
+//!
+//! Standard HUB75 panels pick their rows with a demux instead, see `Rows`, and
+//! `hub75_port` hands out a `HUB75Port` for them. For rows sent by DMA, see
+//! `crate::dma`, `transfer` clocks a row buffer in the way the board does.
+//!
+//! Levels come out of the bit planes sent for each row: the first one after the
+//! row chain moved is bit 0, each one latched after it the next bit. How long
+//! each plane stays lit is up to the caller, so it isn't checked.
+//!
+//! Along the way it checks the timing rules of the protocol, see `Violation`.
+
+use crate::dma::{DataLines, RowBuffer};
+use crate::framebuffer::{HEIGHT, ROWS, WIDTH};
+use crate::palette::Levels;
+use crate::hub::{HUB75Port, HUBAddressPort, HUBDataPort, HUBPort, HUBRowSelectionPort};
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::fmt;
+use embedded_hal::digital::v2::OutputPin;
+
+/// The picture on the panel.
+pub type Frame = [[Levels; WIDTH]; HEIGHT];
+
+/// The lines of the HUB port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Clock,
+    /// Active low, the LEDs are dark while it is high.
+    OutputEnabled,
+    Latch,
+    /// Clock of the row chain, or bit 0 of the row on a demux.
+    A,
+    /// Enables the row drivers when low, or bit 1 of the row on a demux.
+    B,
+    /// Data input of the row chain, or bit 2 of the row on a demux.
+    C,
+    /// Bit 3 of the row on a demux.
+    D,
+    /// Bit 4 of the row on a demux.
+    E,
+    R1,
+    G1,
+    B1,
+    R2,
+    G2,
+    B2,
+}
+
+/// A break of the rules the panel has to be driven by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A row was latched after this many clocks, instead of one per column.
+    ClockCount(u16),
+    /// Latched while the LEDs were lit, so the lit row flashes the data of the next one.
+    LatchWhileLit,
+    /// The row chain moved while the LEDs were lit, so the data shows on two rows.
+    RowShiftWhileLit,
+    /// Lit with more than one row of a half selected.
+    SeveralRows,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ClockCount(clocks) => write!(f, "row latched after {} clocks", clocks),
+            Violation::LatchWhileLit => f.write_str("latched while lit"),
+            Violation::RowShiftWhileLit => f.write_str("row changed while lit"),
+            Violation::SeveralRows => f.write_str("several rows lit at once"),
+        }
+    }
+}
+
+/// How the panel picks the rows it lights.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rows {
+    /// A shift chain, like the panel in the README.
+    ShiftRegister,
+    /// A demux taking the number of the row on A to E, like HUB75 panels.
+    Demux,
+}
+
+pub struct Decoder {
+    rows_by: Rows,
+    levels: [bool; 14],
+    /// Column shift registers, with the six data bits of each column.
+    shifted: [u8; WIDTH],
+    latched: [u8; WIDTH],
+    /// Clocks since the last latch.
+    clocks: u16,
+    /// Lit rows, bit `n` drives row `n` of the panel.
+    rows: u64,
+    /// Bit of the levels being shown.
+    plane: u8,
+    /// Whether the row chain moved since the LEDs were last lit.
+    moved: bool,
+    frame: Frame,
+    frames: u32,
+    violation: Option<Violation>,
+    violations: u32,
+}
+
+impl Decoder {
+    /// A decoder for the panel in the README.
+    pub fn new() -> Decoder {
+        Decoder::with_rows(Rows::ShiftRegister)
+    }
+
+    pub fn with_rows(rows_by: Rows) -> Decoder {
+        Decoder {
+            rows_by,
+            levels: [false; 14],
+            shifted: [0; WIDTH],
+            latched: [0; WIDTH],
+            clocks: 0,
+            rows: match rows_by {
+                Rows::ShiftRegister => 0,
+                // Row 0 is selected with all the lines low
+                Rows::Demux => 1 | 1 << ROWS,
+            },
+            plane: 0,
+            // Whatever is lit first is bit 0
+            moved: true,
+            frame: [[Levels::new(0, 0, 0); WIDTH]; HEIGHT],
+            frames: 0,
+            violation: None,
+            violations: 0,
+        }
+    }
+
+    /// What the panel showed last, each row as it was when it was last lit.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Number of frames started, counted by the bits shifted into the row
+    /// chain, or by the times row 0 was lit after another one on a demux.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// The first rule broken, if any.
+    pub fn violation(&self) -> Option<Violation> {
+        self.violation
+    }
+
+    /// Number of times a rule was broken.
+    pub fn violations(&self) -> u32 {
+        self.violations
+    }
+
+    fn level(&self, signal: Signal) -> bool {
+        self.levels[signal as usize]
+    }
+
+    fn lit(&self) -> bool {
+        match self.rows_by {
+            Rows::ShiftRegister => !self.level(Signal::OutputEnabled) && !self.level(Signal::B),
+            Rows::Demux => !self.level(Signal::OutputEnabled),
+        }
+    }
+
+    /// Row the demux lines point at.
+    fn address(&self) -> usize {
+        let address = [Signal::E, Signal::D, Signal::C, Signal::B, Signal::A]
+            .iter()
+            .fold(0, |address, &signal| address << 1 | self.level(signal) as usize);
+        // Lines past the height of the panel aren't connected
+        address & (ROWS - 1)
+    }
+
+    fn violate(&mut self, violation: Violation) {
+        self.violation.get_or_insert(violation);
+        self.violations = self.violations.saturating_add(1);
+    }
+
+    /// The data lines, laid out like the pixels given to `HUBPort::next_pixel`.
+    fn data(&self) -> u8 {
+        [Signal::R1, Signal::G1, Signal::B1, Signal::R2, Signal::G2, Signal::B2]
+            .iter()
+            .fold(0, |data, &signal| data << 1 | self.level(signal) as u8)
+    }
+
+    fn set(&mut self, signal: Signal, level: bool) {
+        let rising = level && !self.level(signal);
+        let falling = !level && self.level(signal);
+        self.levels[signal as usize] = level;
+        match signal {
+            // `next_pixel` raises the clock, then sets the data, so it is only valid on the falling edge
+            Signal::Clock if falling => {
+                self.shifted.copy_within(1.., 0);
+                self.shifted[WIDTH - 1] = self.data();
+                self.clocks = self.clocks.saturating_add(1);
+            }
+            Signal::Latch if rising => {
+                if self.clocks as usize != WIDTH {
+                    self.violate(Violation::ClockCount(self.clocks));
+                }
+                if self.lit() {
+                    self.violate(Violation::LatchWhileLit);
+                }
+                self.latched = self.shifted;
+                self.clocks = 0;
+                if self.lit() {
+                    self.show();
+                }
+            }
+            Signal::A | Signal::B | Signal::C | Signal::D | Signal::E if self.rows_by == Rows::Demux => {
+                let address = self.address();
+                let rows = 1 << address | 1 << (address + ROWS);
+                if rows == self.rows {
+                    return;
+                }
+                if self.lit() {
+                    self.violate(Violation::RowShiftWhileLit);
+                }
+                self.rows = rows;
+                self.moved = true;
+                if self.lit() {
+                    self.show();
+                }
+            }
+            Signal::A if rising => {
+                if self.lit() {
+                    self.violate(Violation::RowShiftWhileLit);
+                }
+                let first = self.level(Signal::C);
+                self.rows = self.rows << 1 | first as u64;
+                self.moved = true;
+                self.frames = self.frames.wrapping_add(first as u32);
+                if self.lit() {
+                    self.show();
+                }
+            }
+            Signal::OutputEnabled if falling && self.lit() => {
+                // The lines of a demux go through other rows on the way, so only count the ones shown
+                if self.moved && self.rows_by == Rows::Demux && self.address() == 0 {
+                    self.frames = self.frames.wrapping_add(1);
+                }
+                // Levels are kept in a byte
+                self.plane = if self.moved { 0 } else { (self.plane + 1).min(7) };
+                self.moved = false;
+                self.show();
+            }
+            Signal::B if self.lit() => self.show(),
+            _ => {}
+        }
+    }
+
+    /// Lights the selected rows with what was latched.
+    fn show(&mut self) {
+        let half = (1 << ROWS) - 1;
+        let upper = self.rows & half;
+        let lower = self.rows >> ROWS & half;
+        if upper.count_ones() > 1 || lower.count_ones() > 1 {
+            self.violate(Violation::SeveralRows);
+        }
+        let plane = self.plane;
+        // Puts the bits of a pixel into bit `plane` of its levels
+        let light = |levels: &mut Levels, data: u8| {
+            let bit = |level: u8, on: u8| level & !(1 << plane) | (on & 1) << plane;
+            levels.r = bit(levels.r, data >> 2);
+            levels.g = bit(levels.g, data >> 1);
+            levels.b = bit(levels.b, data);
+        };
+        for row in 0..ROWS {
+            for (x, &data) in self.latched.iter().enumerate() {
+                if upper & 1 << row != 0 {
+                    light(&mut self.frame[row][x], data >> 3);
+                }
+                if lower & 1 << row != 0 {
+                    light(&mut self.frame[row + ROWS][x], data);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+/// One line of the HUB port.
+pub struct Pin<'a> {
+    decoder: &'a RefCell<Decoder>,
+    signal: Signal,
+}
+
+impl OutputPin for Pin<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.decoder.borrow_mut().set(self.signal, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.decoder.borrow_mut().set(self.signal, true);
+        Ok(())
+    }
+}
+
+pub type Port<'a> = HUBPort<Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>,
+                            Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>>;
+
+/// A port wired to `decoder`.
+pub fn port(decoder: &RefCell<Decoder>) -> Port<'_> {
+    let pin = |signal| Pin { decoder, signal };
+    HUBPort {
+        clock: pin(Signal::Clock),
+        output_enabled: pin(Signal::OutputEnabled),
+        latch: pin(Signal::Latch),
+        row_selection: HUBRowSelectionPort::new(pin(Signal::A), pin(Signal::B), pin(Signal::C)),
+        data_upper: HUBDataPort { r: pin(Signal::R1), g: pin(Signal::G1), b: pin(Signal::B1) },
+        data_lower: HUBDataPort { r: pin(Signal::R2), g: pin(Signal::G2), b: pin(Signal::B2) },
+    }
+}
+
+/// Clocks `row` into `port` like the timer and the DMA do on the board,
+/// with the bits of each byte wired to the data lines as `lines` says.
+pub fn transfer(port: &mut Port, lines: &DataLines, row: &RowBuffer) {
+    fn set<P: OutputPin>(pin: &mut P, high: bool) {
+        if high {
+            pin.set_high().ok();
+        } else {
+            pin.set_low().ok();
+        }
+    }
+    for &byte in row.iter() {
+        let line = |bit: u8| byte >> bit & 1 != 0;
+        set(&mut port.data_upper.r, line(lines.r1));
+        set(&mut port.data_upper.g, line(lines.g1));
+        set(&mut port.data_upper.b, line(lines.b1));
+        set(&mut port.data_lower.r, line(lines.r2));
+        set(&mut port.data_lower.g, line(lines.g2));
+        set(&mut port.data_lower.b, line(lines.b2));
+        port.clock.set_high().ok();
+        port.clock.set_low().ok();
+    }
+}
+
+pub type HUB75<'a> = HUB75Port<Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>,
+                               Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>, Pin<'a>>;
+
+/// A HUB75 port wired to `decoder`, which should have been made `with_rows(Rows::Demux)`.
+pub fn hub75_port(decoder: &RefCell<Decoder>) -> HUB75<'_> {
+    let pin = |signal| Pin { decoder, signal };
+    HUB75Port {
+        clock: pin(Signal::Clock),
+        output_enabled: pin(Signal::OutputEnabled),
+        latch: pin(Signal::Latch),
+        address: HUBAddressPort { a: pin(Signal::A), b: pin(Signal::B), c: pin(Signal::C), d: pin(Signal::D), e: pin(Signal::E) },
+        data_upper: HUBDataPort { r: pin(Signal::R1), g: pin(Signal::G1), b: pin(Signal::B1) },
+        data_lower: HUBDataPort { r: pin(Signal::R2), g: pin(Signal::G2), b: pin(Signal::B2) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Algorithm, Generator, MazeAlgorithm};
+    use crate::ball::Ball;
+    use crate::display::{draw_ball, draw_maze, Scan, PALETTE, PWM_FREQUENCY};
+    use crate::hub::PanelDriver;
+    use crate::framebuffer::{Colour, Framebuffer, BLUE, GREEN, RED, WHITE};
+    use crate::maze::{Dimensions, Maze, Point};
+    use crate::palette::{Palette, COLOUR_BITS, MAX_LEVEL};
+
+    fn levels(colour: Colour) -> Levels {
+        PALETTE.levels(colour)
+    }
+
+    fn maze(dimensions: Dimensions) -> Maze {
+        let mut maze = Maze::with_dimensions(dimensions);
+        Generator::with_seed(Algorithm::Backtracker, 7).generate(&mut maze, || {});
+        maze
+    }
+
+    fn drawn(maze: &Maze, ball: &Ball) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new();
+        draw_maze(&mut framebuffer, maze);
+        draw_ball(&mut framebuffer, ball);
+        framebuffer
+    }
+
+    /// Scans the whole panel `frames` times, like the TIM15 task.
+    fn scan(framebuffer: &Framebuffer, palette: &Palette, frames: u32) -> Decoder {
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        let mut scan = Scan::new();
+        for _ in 0..frames * ROWS as u32 * COLOUR_BITS as u32 {
+            scan.next(&mut port, framebuffer, palette);
+        }
+        decoder.replace(Decoder::new())
+    }
+
+    fn scanned(maze: &Maze, ball: &Ball, frames: u32) -> Decoder {
+        scan(&drawn(maze, ball), &PALETTE, frames)
+    }
+
+    /// Two frames, the lower half only gets lit once the first bit went through the upper one.
+    fn frame(maze: &Maze, ball: &Ball) -> Frame {
+        *scanned(maze, ball, 2).frame()
+    }
+
+    /// A pixel inside the cell at `location`, away from its walls.
+    fn inside(maze: &Maze, frame: &Frame, location: Point) -> Levels {
+        let size = maze.cell_size() as usize;
+        frame[location.y as usize * size + size / 2][location.x as usize * size + size / 2]
+    }
+
+    #[test]
+    fn scan_keeps_to_the_protocol() {
+        for &dimensions in &[Dimensions::SMALL, Dimensions::MEDIUM, Dimensions::LARGE] {
+            let decoder = scanned(&maze(dimensions), &Ball::new(), 3);
+            assert_eq!(decoder.violation(), None);
+            assert_eq!(decoder.frames(), 3);
+        }
+    }
+
+    #[test]
+    fn walls_and_markers_reach_the_panel() {
+        for &dimensions in &[Dimensions::SMALL, Dimensions::LARGE] {
+            let maze = maze(dimensions);
+            let ball = Ball::from_point(&maze.end, maze.cell_size());
+            let frame = frame(&maze, &ball);
+            let size = maze.cell_size() as usize;
+            assert!((0..dimensions.height as usize * size).all(|y| frame[y][0] == levels(RED)));
+            assert!((0..dimensions.width as usize * size).all(|x| frame[0][x] == levels(RED)));
+            assert_eq!(inside(&maze, &frame, maze.start), levels(BLUE));
+            // The ball sits in the middle of the exit, cells of two pixels have no room for more
+            assert_eq!(inside(&maze, &frame, maze.end), levels(WHITE));
+            if size > 2 {
+                assert_eq!(frame[ball.y as usize / 32 + 1][ball.x as usize / 32], levels(GREEN));
+            }
+        }
+    }
+
+    #[test]
+    fn ball_is_drawn_in_either_half() {
+        let maze = maze(Dimensions::MEDIUM);
+        for &(x, y) in &[(5, 3), (WIDTH - 28, ROWS + 8), (WIDTH / 2 + 6, HEIGHT - 1)] {
+            let (x, y) = (x as u16, y as u16);
+            let ball = Ball { x: x * PWM_FREQUENCY as u16, y: y * PWM_FREQUENCY as u16 };
+            assert_eq!(frame(&maze, &ball)[y as usize][x as usize], levels(WHITE));
+        }
+    }
+
+    #[test]
+    fn every_level_comes_through() {
+        let mut framebuffer = Framebuffer::new();
+        for colour in 0..8 {
+            framebuffer.set(colour, colour, colour);
+            framebuffer.set(colour, colour + ROWS as u8, 7 - colour);
+        }
+        let mut colours = [Levels::default(); 8];
+        for (level, colour) in colours.iter_mut().enumerate() {
+            let level = level as u8 * 2;
+            *colour = Levels::new(level, MAX_LEVEL - level, level / 2);
+        }
+        let decoder = scan(&framebuffer, &Palette::new(colours), 2);
+        for colour in 0..8 {
+            assert_eq!(decoder.frame()[colour][colour], colours[colour]);
+            assert_eq!(decoder.frame()[colour + ROWS][colour], colours[7 - colour]);
+        }
+    }
+
+    #[test]
+    fn lower_half_lights_up_from_the_second_frame() {
+        // Square, so it reaches the bottom of any panel
+        let maze = maze(Dimensions::new(4, 4).unwrap());
+        let first = *scanned(&maze, &Ball::new(), 1).frame();
+        assert!(first[ROWS..].iter().flatten().all(|&levels| levels == Levels::default()));
+        assert!(frame(&maze, &Ball::new())[ROWS..].iter().flatten().any(|&lit| lit == levels(RED)));
+    }
+
+    #[test]
+    fn nothing_is_shown_with_the_rows_disabled() {
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        port.row_selection.b.set_high().ok();
+        let framebuffer = drawn(&maze(Dimensions::SMALL), &Ball::new());
+        let mut scan = Scan::new();
+        for _ in 0..2 * ROWS * COLOUR_BITS as usize {
+            scan.next(&mut port, &framebuffer, &PALETTE);
+        }
+        assert!(decoder.borrow().frame().iter().flatten().all(|&levels| levels == Levels::default()));
+    }
+
+    #[test]
+    fn short_rows_are_caught() {
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        for _ in 0..WIDTH - 1 {
+            port.next_pixel(0);
+        }
+        port.next_page();
+        assert_eq!(decoder.borrow().violation(), Some(Violation::ClockCount(WIDTH as u16 - 1)));
+    }
+
+    #[test]
+    fn latching_while_lit_is_caught() {
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        for _ in 0..WIDTH {
+            port.next_pixel(0);
+        }
+        port.latch.set_high().ok();
+        port.latch.set_low().ok();
+        assert_eq!(decoder.borrow().violation(), Some(Violation::LatchWhileLit));
+    }
+
+    #[test]
+    fn moving_rows_while_lit_is_caught() {
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        port.row_selection.a.set_high().ok();
+        assert_eq!(decoder.borrow().violation(), Some(Violation::RowShiftWhileLit));
+    }
+
+    #[test]
+    fn several_rows_in_a_half_are_caught() {
+        // A page started every row puts two bits in the upper half of the chain
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = port(&decoder);
+        for _ in 0..2 {
+            for _ in 0..WIDTH {
+                port.next_pixel(0);
+            }
+            port.next_page();
+        }
+        assert_eq!(decoder.borrow().violation(), Some(Violation::SeveralRows));
+    }
+
+    #[test]
+    fn hub75_panels_show_the_same() {
+        let framebuffer = drawn(&maze(Dimensions::MEDIUM), &Ball::new());
+        let decoder = RefCell::new(Decoder::with_rows(Rows::Demux));
+        let mut port = hub75_port(&decoder);
+        let mut scan = Scan::new();
+        for _ in 0..3 * ROWS * COLOUR_BITS as usize {
+            scan.next(&mut port, &framebuffer, &PALETTE);
+        }
+        let decoder = decoder.borrow();
+        assert_eq!(decoder.violation(), None);
+        assert_eq!(decoder.frames(), 3);
+        assert!(decoder.frame()[..] == scan_frame(&framebuffer)[..]);
+    }
+
+    fn scan_frame(framebuffer: &Framebuffer) -> Frame {
+        *scan(framebuffer, &PALETTE, 2).frame()
+    }
+
+    #[test]
+    fn hub75_rows_changing_while_lit_are_caught() {
+        let decoder = RefCell::new(Decoder::with_rows(Rows::Demux));
+        let mut port = hub75_port(&decoder);
+        for _ in 0..WIDTH {
+            port.next_pixel(0);
+        }
+        port.show(3);
+        assert_eq!(decoder.borrow().violation(), None);
+        port.address.a.set_low().ok();
+        assert_eq!(decoder.borrow().violation(), Some(Violation::RowShiftWhileLit));
+    }
+}
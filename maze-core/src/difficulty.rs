@@ -0,0 +1,134 @@
+//! How hard a maze is: its size, how twisty its corridors are and how many dead ends it has.
+
+use crate::algorithms::{braid, Generator, MazeAlgorithm};
+use crate::maze::{Dimensions, Maze};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Difficulty {
+	pub dimensions: Dimensions,
+	/// Chance out of 256 for the backtracker to carry on straight, see `MazeGenerator::straightness`.
+	/// Long straight corridors are easier to roll the ball through.
+	pub straightness: u8,
+	/// Percentage of the dead ends opened up after generation, see `braid`.
+	pub braid: u8,
+}
+impl Difficulty {
+	pub const EASY: Difficulty = Difficulty { dimensions: Dimensions::SMALL, straightness: 192, braid: 60 };
+	pub const MEDIUM: Difficulty = Difficulty { dimensions: Dimensions::MEDIUM, straightness: 128, braid: 25 };
+	pub const HARD: Difficulty = Difficulty { dimensions: Dimensions::LARGE, straightness: 0, braid: 0 };
+
+	/// The difficulty of every level, first to last.
+	pub const LEVELS: [Difficulty; 6] = [
+		Difficulty::EASY,
+		Difficulty { dimensions: Dimensions::SMALL, straightness: 64, braid: 10 },
+		Difficulty::MEDIUM,
+		Difficulty { dimensions: Dimensions::MEDIUM, straightness: 32, braid: 0 },
+		Difficulty { dimensions: Dimensions::LARGE, straightness: 96, braid: 20 },
+		Difficulty::HARD,
+	];
+
+	/// Difficulty of `level`, counted from 0. Levels past the last one stay at the hardest.
+	pub fn for_level(level: u8) -> Difficulty {
+		let last = Difficulty::LEVELS.len() - 1;
+		Difficulty::LEVELS[core::cmp::min(level as usize, last)]
+	}
+
+	/// Generates a maze of this difficulty with `generator`, starting from `maze.start`.
+	///
+	/// The straightness only applies to the backtracker, the other algorithms have
+	/// their own texture. The braiding works with any of them.
+	pub fn generate<F>(&self, generator: &mut Generator, maze: &mut Maze, mut delay: F)
+	where F: FnMut() {
+		self.start(generator, maze);
+		while generator.step(maze).is_some() {
+			delay();
+		}
+		self.finish(maze);
+	}
+
+	/// Begins a maze of this difficulty, for `generator.step` to carve one passage at a time
+	/// and `finish` to complete once it's done.
+	pub fn start(&self, generator: &mut Generator, maze: &mut Maze) {
+		maze.dimensions = self.dimensions;
+		if let Generator::Backtracker(backtracker) = generator {
+			backtracker.straightness = self.straightness;
+		}
+		generator.start(maze);
+	}
+
+	/// Opens up the dead ends of a maze carved since `start`.
+	pub fn finish(&self, maze: &mut Maze) {
+		braid(maze, self.braid);
+	}
+}
+
+impl Default for Difficulty {
+	fn default() -> Difficulty {
+		Difficulty::for_level(0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algorithms::Algorithm;
+	use crate::maze::{Direction, Point};
+
+	fn generated(difficulty: Difficulty, seed: u64) -> Maze {
+		let mut maze = Maze::new();
+		difficulty.generate(&mut Generator::with_seed(Algorithm::Backtracker, seed), &mut maze, || {});
+		maze
+	}
+
+	/// Number of cells where the corridor changes direction or branches.
+	fn turns(maze: &Maze) -> usize {
+		maze.dimensions.points().filter(|&p: &Point| {
+			let horizontal = maze.is_open(p, Direction::Left) || maze.is_open(p, Direction::Right);
+			let vertical = maze.is_open(p, Direction::Top) || maze.is_open(p, Direction::Bottom);
+			horizontal && vertical
+		}).count()
+	}
+
+	#[test]
+	fn levels_get_harder_and_then_stay_there() {
+		assert_eq!(Difficulty::for_level(0), Difficulty::EASY);
+		assert_eq!(Difficulty::for_level(5), Difficulty::HARD);
+		assert_eq!(Difficulty::for_level(200), Difficulty::HARD);
+		for pair in Difficulty::LEVELS.windows(2) {
+			assert!(pair[0].dimensions.cells() <= pair[1].dimensions.cells());
+		}
+	}
+
+	#[test]
+	fn generate_uses_the_dimensions() {
+		for &difficulty in &Difficulty::LEVELS {
+			let maze = generated(difficulty, 5);
+			assert_eq!(maze.dimensions, difficulty.dimensions);
+			assert!(difficulty.dimensions.contains(maze.end));
+			assert_eq!(maze.reachable_from(maze.start), difficulty.dimensions.cells());
+		}
+	}
+
+	#[test]
+	fn straightness_makes_fewer_turns() {
+		let twisty = Difficulty { straightness: 0, braid: 0, ..Difficulty::HARD };
+		let straight = Difficulty { straightness: 240, ..twisty };
+		let mut twisty_turns = 0;
+		let mut straight_turns = 0;
+		for seed in 0..8 {
+			twisty_turns += turns(&generated(twisty, seed));
+			straight_turns += turns(&generated(straight, seed));
+		}
+		assert!(straight_turns < twisty_turns, "{} vs {}", straight_turns, twisty_turns);
+	}
+
+	#[test]
+	fn unbiased_backtracker_is_unchanged() {
+		let mut maze = Maze::new();
+		Generator::with_seed(Algorithm::Backtracker, 5).generate(&mut maze, || {});
+		let hard = generated(Difficulty::HARD, 5);
+		assert!(Dimensions::LARGE.points().all(|p| {
+			maze.bitmap_left.get(p) == hard.bitmap_left.get(p) && maze.bitmap_top.get(p) == hard.bitmap_top.get(p)
+		}));
+	}
+}
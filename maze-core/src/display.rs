@@ -0,0 +1,306 @@
+use crate::algorithms::Step;
+use crate::hub::PanelDriver;
+use crate::maze::{Direction, Point, Maze};
+use crate::ball::Ball;
+use crate::framebuffer::{Colour, Framebuffer, BLACK, BLUE, GREEN, RED, ROWS, WHITE};
+use crate::dma::{PortPalette, RowBuffer};
+use crate::palette::{Levels, Palette, COLOUR_BITS};
+
+/// Number of sub-pixel steps per screen pixel in ball coordinates.
+pub const PWM_FREQUENCY: u8 = 32;
+/// Size of the LED panel in pixels, picked by the `panel-*` features.
+///
+/// The panel is scanned two rows at a time, one in each half, so it has to be
+/// a 1/`SCREEN_HEIGHT / 2` scan panel, as HUB75 and HUB75E panels usually are.
+#[cfg(not(any(feature = "panel-64x32", feature = "panel-64x64")))]
+pub const SCREEN_WIDTH: u8 = 128;
+#[cfg(not(any(feature = "panel-64x32", feature = "panel-64x64")))]
+pub const SCREEN_HEIGHT: u8 = 64;
+#[cfg(feature = "panel-64x32")]
+pub const SCREEN_WIDTH: u8 = 64;
+#[cfg(feature = "panel-64x32")]
+pub const SCREEN_HEIGHT: u8 = 32;
+#[cfg(feature = "panel-64x64")]
+pub const SCREEN_WIDTH: u8 = 64;
+#[cfg(feature = "panel-64x64")]
+pub const SCREEN_HEIGHT: u8 = 64;
+#[cfg(all(feature = "panel-64x32", feature = "panel-64x64"))]
+compile_error!("pick only one of the panel-* features");
+
+/// Where the ball has been. It takes the place of cyan, which the game doesn't use.
+pub const TRAIL: Colour = GREEN | BLUE;
+
+/// Colours of the game: dim walls and trail, so the ball and the markers stand out.
+pub const PALETTE: Palette = Palette::new([
+    Levels::shade(0, 0, 0),
+    Levels::shade(0, 64, 255),
+    Levels::shade(0, 255, 64),
+    Levels::shade(64, 64, 128),
+    Levels::shade(160, 0, 0),
+    Levels::shade(255, 0, 255),
+    Levels::shade(255, 255, 0),
+    Levels::shade(255, 255, 255),
+]);
+
+/// Colour of the maze at screen pixel `(x, y)`: walls, start and end.
+///
+/// Each cell draws its top wall on its first row and its left wall on its first
+/// column. The pixel where they meet is lit if any wall ends there, so corners
+/// are closed. The right and bottom border go just past the last cells, unless
+/// the maze reaches the edge of the panel there.
+pub fn maze_pixel(maze: &Maze, x: u8, y: u8) -> Colour {
+    let size = maze.cell_size();
+    let right = maze.dimensions.width * size;
+    let bottom = maze.dimensions.height * size;
+    if (x == right && y <= bottom) || (y == bottom && x <= right) {
+        return RED;
+    }
+    let location = Point { x: x / size, y: y / size };
+    if !maze.dimensions.contains(location) {
+        return BLACK;
+    }
+    let wall = |present: bool| if present { RED } else { BLACK };
+    let top = y == location.y * size;
+    let left = x == location.x * size;
+    match (top, left) {
+        (true, true) => wall(maze.bitmap_top.get(location)
+            || maze.bitmap_left.get(location)
+            || (location.x > 0 && maze.bitmap_top.get(Point { x: location.x - 1, y: location.y }))
+            || (location.y > 0 && maze.bitmap_left.get(Point { x: location.x, y: location.y - 1 }))),
+        (true, false) => wall(maze.bitmap_top.get(location)),
+        (false, true) => wall(maze.bitmap_left.get(location)),
+        (false, false) if location == maze.start => BLUE,
+        (false, false) if location == maze.end => GREEN,
+        (false, false) => BLACK,
+    }
+}
+
+/// Draws row `y` of the maze, so a redraw can be split into short pieces.
+pub fn draw_maze_row(framebuffer: &mut Framebuffer, maze: &Maze, y: u8) {
+    for x in 0 .. SCREEN_WIDTH {
+        framebuffer.set(x, y, maze_pixel(maze, x, y));
+    }
+}
+
+/// Draws cell `location` of the maze, its top and left walls included.
+pub fn draw_maze_cell(framebuffer: &mut Framebuffer, maze: &Maze, location: Point) {
+    if !maze.dimensions.contains(location) {
+        return;
+    }
+    let size = maze.cell_size();
+    for y in location.y * size .. (location.y + 1) * size {
+        for x in location.x * size .. (location.x + 1) * size {
+            framebuffer.set(x, y, maze_pixel(maze, x, y));
+        }
+    }
+}
+
+/// Draws what `step` changed: the wall it broke and the corners at both of its ends.
+pub fn draw_step(framebuffer: &mut Framebuffer, maze: &Maze, step: &Step) {
+    // The wall is drawn by the cell below or right of it, and the corner
+    // at its far end by the next cell along it
+    let (cell, along) = match step.direction {
+        Direction::Top => (step.location, Direction::Right),
+        Direction::Left => (step.location, Direction::Bottom),
+        Direction::Bottom => (step.location.dir(Direction::Bottom), Direction::Right),
+        Direction::Right => (step.location.dir(Direction::Right), Direction::Bottom),
+    };
+    draw_maze_cell(framebuffer, maze, cell);
+    if let Some(next) = maze.dimensions.neighbour(cell, along) {
+        draw_maze_cell(framebuffer, maze, next);
+    }
+}
+
+/// Draws the whole maze, over everything else.
+pub fn draw_maze(framebuffer: &mut Framebuffer, maze: &Maze) {
+    for y in 0 .. SCREEN_HEIGHT {
+        draw_maze_row(framebuffer, maze, y);
+    }
+}
+
+/// Screen pixel of the ball.
+fn ball_pixel(ball: &Ball) -> (u8, u8) {
+    ((ball.x / PWM_FREQUENCY as u16) as u8, (ball.y / PWM_FREQUENCY as u16) as u8)
+}
+
+pub fn draw_ball(framebuffer: &mut Framebuffer, ball: &Ball) {
+    let (x, y) = ball_pixel(ball);
+    framebuffer.set(x, y, WHITE);
+}
+
+/// Puts back the maze where the ball was drawn, leaving a trail on empty pixels.
+pub fn erase_ball(framebuffer: &mut Framebuffer, maze: &Maze, ball: &Ball) {
+    let (x, y) = ball_pixel(ball);
+    let colour = match maze_pixel(maze, x, y) {
+        BLACK => TRAIL,
+        colour => colour,
+    };
+    framebuffer.set(x, y, colour);
+}
+
+/// How far the scan of the panel got.
+///
+/// The levels of the palette are shown by binary code modulation: each row is
+/// sent once per bit of the levels, from bit 0 up, and bit `n` has to stay lit
+/// twice as long as bit `n - 1`.
+pub struct Scan {
+    row: u8,
+    plane: u8,
+}
+
+impl Scan {
+    pub const fn new() -> Scan {
+        Scan { row: 0, plane: 0 }
+    }
+
+    /// Sends the next bit plane of the current row and shows it, then returns
+    /// its number `n`. It should stay lit for `1 << n` units of time, until the
+    /// next call.
+    ///
+    /// The panel lights a row of each half at once, so the scan goes through
+    /// rows 0 to `ROWS - 1`. Going back to row 0 starts a new frame.
+    pub fn next<P: PanelDriver>(&mut self, port: &mut P, framebuffer: &Framebuffer, palette: &Palette) -> u8 {
+        for pair in framebuffer.row(self.row) {
+            port.next_pixel(palette.plane(self.plane, pair));
+        }
+        self.latch(port)
+    }
+
+    /// Puts what `next` would send for the current row and plane in `buffer`,
+    /// laid out for `palette`, and moves on without touching the panel.
+    ///
+    /// This is the half of `next` that doesn't need the port, for when the
+    /// data goes out by DMA. `latch` is the other half, run by a second `Scan`
+    /// once the data is in.
+    pub fn fill(&mut self, framebuffer: &Framebuffer, palette: &PortPalette, buffer: &mut RowBuffer) {
+        for (byte, pair) in buffer.iter_mut().zip(framebuffer.row(self.row)) {
+            *byte = palette.byte(self.plane, pair);
+        }
+        self.advance();
+    }
+
+    /// Shows the data shifted in for the current row and plane, moves on and
+    /// returns the plane shown, like `next`.
+    pub fn latch<P: PanelDriver>(&mut self, port: &mut P) -> u8 {
+        let plane = self.plane;
+        port.show(self.row);
+        self.advance();
+        plane
+    }
+
+    fn advance(&mut self) {
+        self.plane += 1;
+        if self.plane == COLOUR_BITS {
+            self.plane = 0;
+            self.row = (self.row + 1) % ROWS as u8;
+        }
+    }
+}
+
+impl Default for Scan {
+    fn default() -> Scan {
+        Scan::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{Algorithm, Generator, MazeAlgorithm};
+    use crate::maze::Dimensions;
+
+    fn maze(dimensions: Dimensions) -> Maze {
+        let mut maze = Maze::with_dimensions(dimensions);
+        Generator::with_seed(Algorithm::Prim, 3).generate(&mut maze, || {});
+        maze
+    }
+
+    #[test]
+    fn maze_fills_the_screen_from_the_top_left() {
+        for &dimensions in &[Dimensions::SMALL, Dimensions::MEDIUM, Dimensions::LARGE] {
+            let maze = maze(dimensions);
+            let size = maze.cell_size();
+            let width = dimensions.width * size;
+            let height = dimensions.height * size;
+            assert!((0 .. width).all(|x| maze_pixel(&maze, x, 0) == RED));
+            assert!((0 .. height).all(|y| maze_pixel(&maze, 0, y) == RED));
+            assert!((width + 1 .. SCREEN_WIDTH).all(|x| (0 .. SCREEN_HEIGHT).all(|y| maze_pixel(&maze, x, y) == BLACK)));
+            let middle = |p: Point| maze_pixel(&maze, p.x * size + size / 2, p.y * size + size / 2);
+            assert_eq!(middle(maze.start), BLUE);
+            assert_eq!(middle(maze.end), GREEN);
+        }
+    }
+
+    #[test]
+    fn maze_short_of_the_panel_is_closed_all_round() {
+        let maze = maze(Dimensions { width: 5, height: 3 });
+        let size = maze.cell_size();
+        let (right, bottom) = (5 * size, 3 * size);
+        assert!(right < SCREEN_WIDTH && bottom < SCREEN_HEIGHT);
+        assert!((0 ..= bottom).all(|y| maze_pixel(&maze, 0, y) == RED && maze_pixel(&maze, right, y) == RED));
+        assert!((0 ..= right).all(|x| maze_pixel(&maze, x, 0) == RED && maze_pixel(&maze, x, bottom) == RED));
+        assert_eq!(maze_pixel(&maze, right + 1, bottom), BLACK);
+        assert_eq!(maze_pixel(&maze, right, bottom + 1), BLACK);
+    }
+
+    #[test]
+    fn steps_draw_the_maze_as_it_is_carved() {
+        for &algorithm in &Algorithm::ALL {
+            let mut generator = Generator::with_seed(algorithm, 8);
+            let mut maze = Maze::with_dimensions(Dimensions::MEDIUM);
+            generator.start(&mut maze);
+            let mut framebuffer = Framebuffer::new();
+            draw_maze(&mut framebuffer, &maze);
+            let mut end = maze.end;
+            while let Some(step) = generator.step(&mut maze) {
+                draw_step(&mut framebuffer, &maze, &step);
+                // The backtracker moves the exit along as it goes
+                if maze.end != end {
+                    draw_maze_cell(&mut framebuffer, &maze, end);
+                    end = maze.end;
+                }
+            }
+            draw_maze_cell(&mut framebuffer, &maze, end);
+            draw_maze_cell(&mut framebuffer, &maze, maze.end);
+            let mut whole = Framebuffer::new();
+            draw_maze(&mut whole, &maze);
+            for y in 0 .. SCREEN_HEIGHT {
+                for x in 0 .. SCREEN_WIDTH {
+                    assert_eq!(framebuffer.get(x, y), whole.get(x, y), "{:?} at ({}, {})", algorithm, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn erasing_the_ball_restores_the_maze() {
+        let maze = maze(Dimensions::MEDIUM);
+        let mut framebuffer = Framebuffer::new();
+        draw_maze(&mut framebuffer, &maze);
+        let ball = Ball::from_point(&maze.start, maze.cell_size());
+        let (x, y) = ball_pixel(&ball);
+        draw_ball(&mut framebuffer, &ball);
+        assert_eq!(framebuffer.get(x, y), WHITE);
+        erase_ball(&mut framebuffer, &maze, &ball);
+        assert_eq!(framebuffer.get(x, y), BLUE);
+    }
+
+    #[test]
+    fn ball_leaves_a_trail_off_the_walls() {
+        let maze = maze(Dimensions::SMALL);
+        let mut framebuffer = Framebuffer::new();
+        draw_maze(&mut framebuffer, &maze);
+        let cell = maze.dimensions.points()
+            .find(|&p| p != maze.start && p != maze.end && maze.bitmap_left.get(p))
+            .unwrap();
+        let (x, y) = (cell.x * maze.cell_size(), cell.y * maze.cell_size() + 3);
+        let at = |x: u8, y: u8| Ball { x: x as u16 * PWM_FREQUENCY as u16, y: y as u16 * PWM_FREQUENCY as u16 };
+        let wall = framebuffer.get(x, y);
+        assert_eq!(wall, RED);
+        assert_eq!(framebuffer.get(x + 2, y), BLACK);
+        erase_ball(&mut framebuffer, &maze, &at(x, y));
+        erase_ball(&mut framebuffer, &maze, &at(x + 2, y));
+        assert_eq!(framebuffer.get(x, y), wall);
+        assert_eq!(framebuffer.get(x + 2, y), TRAIL);
+    }
+}
@@ -0,0 +1,210 @@
+//! Sending rows to the panel by DMA instead of toggling pins.
+//!
+//! On the board the six data lines of the HUB port sit on the low byte of one
+//! GPIO port, and the port takes byte writes to its output register without
+//! touching the pins above. A timer drives the panel clock and requests a
+//! transfer for each column, so a row goes out as a plain array of bytes, one
+//! per column, with nothing for the CPU to do until the latch.
+//!
+//! `DmaScan` keeps two of those arrays, so the next row is ready by the time
+//! the current one has been shifted in.
+
+use crate::display::Scan;
+use crate::framebuffer::{Framebuffer, WIDTH};
+use crate::hub::PanelDriver;
+use crate::palette::{Palette, COLOUR_BITS};
+
+/// One row as written to the port, a byte per column.
+pub type RowBuffer = [u8; WIDTH];
+
+/// Bit of the port byte each data line is wired to, from 0 to 7.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataLines {
+    pub r1: u8,
+    pub g1: u8,
+    pub b1: u8,
+    pub r2: u8,
+    pub g2: u8,
+    pub b2: u8,
+}
+
+impl DataLines {
+    /// Port byte putting `pixel`, as `HUBPort::next_pixel` takes it, on the data lines.
+    pub const fn byte(&self, pixel: u8) -> u8 {
+        (pixel >> 5 & 1) << self.r1
+            | (pixel >> 4 & 1) << self.g1
+            | (pixel >> 3 & 1) << self.b1
+            | (pixel >> 2 & 1) << self.r2
+            | (pixel >> 1 & 1) << self.g2
+            | (pixel & 1) << self.b2
+    }
+}
+
+/// A `Palette` with its planes already laid out for the port.
+pub struct PortPalette {
+    planes: [[u8; 64]; COLOUR_BITS as usize],
+}
+
+impl PortPalette {
+    pub const fn new(palette: &Palette, lines: &DataLines) -> PortPalette {
+        let mut planes = [[0; 64]; COLOUR_BITS as usize];
+        let mut plane = 0;
+        while plane < COLOUR_BITS as usize {
+            let mut pair = 0;
+            while pair < 64 {
+                planes[plane][pair] = lines.byte(palette.plane(plane as u8, pair as u8));
+                pair += 1;
+            }
+            plane += 1;
+        }
+        PortPalette { planes }
+    }
+
+    /// What to write to the port for a byte of the framebuffer while showing bit `plane` of the levels.
+    pub fn byte(&self, plane: u8, pair: u8) -> u8 {
+        self.planes[plane as usize][(pair & 0b11_1111) as usize]
+    }
+}
+
+/// Scan of the panel with the data going out by DMA.
+///
+/// Call `latch` once the transfer `shift` started last is done, then `shift`
+/// for the next one. Between them they do what `Scan::next` does, except for
+/// clocking the data out. Until the first `shift` there is nothing to latch,
+/// so a scan can start with either.
+pub struct DmaScan {
+    /// Next row and plane to be shown.
+    shown: Scan,
+    /// Next row and plane to be prepared, one ahead of the one being shifted in.
+    filled: Scan,
+    buffers: [RowBuffer; 2],
+    /// Buffer holding the row `shift` sends next.
+    next: usize,
+    /// Whether that buffer has been filled yet, it only hasn't before the first `shift`.
+    primed: bool,
+}
+
+impl DmaScan {
+    pub const fn new() -> DmaScan {
+        DmaScan { shown: Scan::new(), filled: Scan::new(), buffers: [[0; WIDTH]; 2], next: 0, primed: false }
+    }
+
+    /// Shows the row shifted in by the last transfer, and returns its plane like `Scan::next`.
+    /// Returns `None` without touching the panel before the first `shift`.
+    pub fn latch<P: PanelDriver>(&mut self, port: &mut P) -> Option<u8> {
+        if !self.primed {
+            return None;
+        }
+        Some(self.shown.latch(port))
+    }
+
+    /// Hands the next row to `start`, which starts its transfer, then prepares
+    /// the one after it in the other buffer.
+    ///
+    /// The buffer given to `start` is left alone until the next call, so the
+    /// transfer can keep reading it after `start` returns.
+    pub fn shift<F>(&mut self, framebuffer: &Framebuffer, palette: &PortPalette, start: F)
+    where F: FnOnce(&RowBuffer) {
+        if !self.primed {
+            self.filled.fill(framebuffer, palette, &mut self.buffers[self.next]);
+            self.primed = true;
+        }
+        start(&self.buffers[self.next]);
+        self.filled.fill(framebuffer, palette, &mut self.buffers[self.next ^ 1]);
+        self.next ^= 1;
+    }
+}
+
+impl Default for DmaScan {
+    fn default() -> DmaScan {
+        DmaScan::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{self, transfer, Decoder};
+    use crate::display::PALETTE;
+    use crate::framebuffer::{BLUE, GREEN, RED, ROWS, WHITE};
+    use core::cell::RefCell;
+
+    /// How the board wires the data lines to PC0 to PC5.
+    const BOARD: DataLines = DataLines { r1: 0, g1: 1, b1: 2, r2: 3, g2: 4, b2: 5 };
+
+    fn picture() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new();
+        for (i, &colour) in [RED, GREEN, BLUE, WHITE, RED | BLUE].iter().enumerate() {
+            let i = i as u8;
+            framebuffer.set(i * 7, i * 13, colour);
+            framebuffer.set(127 - i, 63 - i * 5, colour);
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn lines_take_their_bits() {
+        assert_eq!(BOARD.byte(0b100_000), 0b000_001);
+        assert_eq!(BOARD.byte(0b000_001), 0b100_000);
+        assert_eq!(BOARD.byte(0b011_010), 0b010_110);
+        let swapped = DataLines { r1: 7, g1: 6, b1: 5, r2: 2, g2: 1, b2: 0 };
+        assert_eq!(swapped.byte(0b111_111), 0b1110_0111);
+        let palette = PortPalette::new(&PALETTE, &swapped);
+        assert_eq!(palette.byte(3, WHITE << 3 | RED), swapped.byte(PALETTE.plane(3, WHITE << 3 | RED)));
+    }
+
+    #[test]
+    fn dma_scan_shows_what_scan_does() {
+        let framebuffer = picture();
+        let frames = 2 * ROWS * COLOUR_BITS as usize;
+
+        let expected = RefCell::new(Decoder::new());
+        let mut port = decoder::port(&expected);
+        let mut scan = Scan::new();
+        let planes: Vec<u8> = (0 .. frames).map(|_| scan.next(&mut port, &framebuffer, &PALETTE)).collect();
+
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = decoder::port(&decoder);
+        let palette = PortPalette::new(&PALETTE, &BOARD);
+        let mut scan = DmaScan::new();
+        scan.shift(&framebuffer, &palette, |buffer| transfer(&mut port, &BOARD, buffer));
+        for &plane in planes.iter() {
+            assert_eq!(scan.latch(&mut port), Some(plane));
+            scan.shift(&framebuffer, &palette, |buffer| transfer(&mut port, &BOARD, buffer));
+        }
+
+        let decoder = decoder.borrow();
+        assert_eq!(decoder.violation(), None);
+        assert_eq!(decoder.frames(), expected.borrow().frames());
+        assert!(decoder.frame()[..] == expected.borrow().frame()[..]);
+        assert_eq!(decoder.frame()[13][7], PALETTE.levels(GREEN));
+    }
+
+    #[test]
+    fn dma_scan_starts_in_the_order_of_the_firmware() {
+        let framebuffer = picture();
+        let frames = 2 * ROWS * COLOUR_BITS as usize;
+
+        let expected = RefCell::new(Decoder::new());
+        let mut port = decoder::port(&expected);
+        let mut scan = Scan::new();
+        let planes: Vec<u8> = (0 .. frames).map(|_| scan.next(&mut port, &framebuffer, &PALETTE)).collect();
+
+        // `tick` latches first and shifts second, from the very first interrupt
+        let decoder = RefCell::new(Decoder::new());
+        let mut port = decoder::port(&decoder);
+        let palette = PortPalette::new(&PALETTE, &BOARD);
+        let mut scan = DmaScan::new();
+        let mut latched = Vec::new();
+        for _ in 0 ..= frames {
+            latched.extend(scan.latch(&mut port));
+            scan.shift(&framebuffer, &palette, |buffer| transfer(&mut port, &BOARD, buffer));
+        }
+
+        assert_eq!(latched, planes);
+        let decoder = decoder.borrow();
+        assert_eq!(decoder.violation(), None);
+        assert!(decoder.frame()[..] == expected.borrow().frame()[..]);
+        assert_eq!(decoder.frame()[13][7], PALETTE.levels(GREEN));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::algorithms::next_seed;
+
+/// Turns a bunch of noisy readings into a seed.
+///
+/// Meant for things like the lowest bits of ADC conversions or timer values at
+/// boot. Each sample on its own carries very little randomness, so collect a
+/// few dozen of them before calling `seed`.
+pub struct Entropy {
+    state: u64,
+    samples: u16,
+}
+
+impl Entropy {
+    pub fn new() -> Entropy {
+        Entropy { state: 0, samples: 0 }
+    }
+
+    pub fn push(&mut self, sample: u16) {
+        self.state = self.state.rotate_left(11) ^ sample as u64;
+        self.state = self.state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    /// Number of samples pushed so far.
+    pub fn samples(&self) -> u16 {
+        self.samples
+    }
+
+    pub fn seed(&self) -> u64 {
+        next_seed(self.state)
+    }
+}
+
+impl Default for Entropy {
+    fn default() -> Entropy {
+        Entropy::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_of(samples: &[u16]) -> u64 {
+        let mut entropy = Entropy::new();
+        for &sample in samples {
+            entropy.push(sample);
+        }
+        entropy.seed()
+    }
+
+    #[test]
+    fn same_samples_same_seed() {
+        assert_eq!(seed_of(&[1, 2, 3]), seed_of(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn every_sample_matters() {
+        let base = seed_of(&[2048, 2050, 2047, 2049]);
+        assert_ne!(base, seed_of(&[2048, 2050, 2047, 2048]));
+        assert_ne!(base, seed_of(&[2049, 2050, 2047, 2049]));
+        // Order too, not just the sum of the readings
+        assert_ne!(base, seed_of(&[2050, 2048, 2047, 2049]));
+    }
+
+    #[test]
+    fn counts_samples() {
+        let mut entropy = Entropy::new();
+        entropy.push(0);
+        entropy.push(0);
+        assert_eq!(entropy.samples(), 2);
+    }
+}
@@ -0,0 +1,223 @@
+//! Binary encoding of a maze, for sending it over serial or keeping it in flash.
+//!
+//! All numbers are little endian:
+//!
+//! | offset | size | content                                          |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 2    | magic, `MZ`                                      |
+//! | 2      | 1    | format version, `FORMAT_VERSION`                 |
+//! | 3      | 2    | width, height                                    |
+//! | 5      | 1    | algorithm id, see `Algorithm`                    |
+//! | 6      | 8    | seed                                             |
+//! | 14     | 4    | start x, y, end x, y                             |
+//! | 18     | n    | walls, two bits per cell                         |
+//! | 18 + n | 2    | CRC-16/CCITT-FALSE of everything before it       |
+//!
+//! The walls are packed row by row from the least significant bit: the left
+//! wall of a cell, then its top wall. Only the cells within the dimensions
+//! are stored, so a small maze only takes a few bytes.
+
+use crate::algorithms::Algorithm;
+use crate::crc::crc16;
+use crate::maze::{Dimensions, Maze, Point};
+use core::fmt;
+use core::ops::Deref;
+
+pub const FORMAT_VERSION: u8 = 1;
+const MAGIC: [u8; 2] = *b"MZ";
+const HEADER_LEN: usize = 18;
+const CHECKSUM_LEN: usize = 2;
+/// Length of the encoding of the largest maze.
+pub const MAX_ENCODED_LEN: usize = HEADER_LEN + wall_bytes(Dimensions::LARGE) + CHECKSUM_LEN;
+
+const fn wall_bytes(dimensions: Dimensions) -> usize {
+	let bits = dimensions.width as usize * dimensions.height as usize * 2;
+	bits.div_ceil(8)
+}
+
+/// Length of the encoding of a maze of these dimensions.
+pub const fn encoded_len(dimensions: Dimensions) -> usize {
+	HEADER_LEN + wall_bytes(dimensions) + CHECKSUM_LEN
+}
+
+/// An encoded maze. Dereferences to the bytes.
+pub struct MazeBytes {
+	data: [u8; MAX_ENCODED_LEN],
+	len: usize,
+}
+impl Deref for MazeBytes {
+	type Target = [u8];
+	fn deref(&self) -> &[u8] {
+		&self.data[..self.len]
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+	/// Not as many bytes as the header says there should be.
+	WrongLength,
+	BadMagic,
+	UnsupportedVersion(u8),
+	InvalidDimensions,
+	UnknownAlgorithm(u8),
+	/// Start or end outside of the maze.
+	PointOutOfBounds,
+	BadChecksum,
+}
+
+impl fmt::Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FormatError::WrongLength => f.write_str("wrong length"),
+			FormatError::BadMagic => f.write_str("not a maze"),
+			FormatError::UnsupportedVersion(version) => write!(f, "unsupported version {}", version),
+			FormatError::InvalidDimensions => f.write_str("invalid dimensions"),
+			FormatError::UnknownAlgorithm(id) => write!(f, "unknown algorithm {}", id),
+			FormatError::PointOutOfBounds => f.write_str("start or end outside of the maze"),
+			FormatError::BadChecksum => f.write_str("bad checksum"),
+		}
+	}
+}
+
+impl Maze {
+	pub fn to_bytes(&self) -> MazeBytes {
+		let dimensions = self.dimensions;
+		let len = encoded_len(dimensions);
+		let mut data = [0; MAX_ENCODED_LEN];
+		data[0..2].copy_from_slice(&MAGIC);
+		data[2] = FORMAT_VERSION;
+		data[3] = dimensions.width;
+		data[4] = dimensions.height;
+		data[5] = self.algorithm as u8;
+		data[6..14].copy_from_slice(&self.seed.to_le_bytes());
+		data[14..18].copy_from_slice(&[self.start.x, self.start.y, self.end.x, self.end.y]);
+		let walls = &mut data[HEADER_LEN..len - CHECKSUM_LEN];
+		for (index, location) in dimensions.points().enumerate() {
+			let bits = self.bitmap_left.get(location) as u8 | (self.bitmap_top.get(location) as u8) << 1;
+			walls[index / 4] |= bits << ((index % 4) * 2);
+		}
+		let checksum = crc16(&data[..len - CHECKSUM_LEN]);
+		data[len - CHECKSUM_LEN..len].copy_from_slice(&checksum.to_le_bytes());
+		MazeBytes { data, len }
+	}
+
+	/// Decodes a maze written by `to_bytes`. `bytes` has to be exactly one encoded maze.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Maze, FormatError> {
+		if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+			return Err(FormatError::WrongLength);
+		}
+		if bytes[0..2] != MAGIC {
+			return Err(FormatError::BadMagic);
+		}
+		if bytes[2] != FORMAT_VERSION {
+			return Err(FormatError::UnsupportedVersion(bytes[2]));
+		}
+		let dimensions = Dimensions::new(bytes[3], bytes[4]).ok_or(FormatError::InvalidDimensions)?;
+		let len = encoded_len(dimensions);
+		if bytes.len() != len {
+			return Err(FormatError::WrongLength);
+		}
+		let checksum = u16::from_le_bytes([bytes[len - 2], bytes[len - 1]]);
+		if crc16(&bytes[..len - CHECKSUM_LEN]) != checksum {
+			return Err(FormatError::BadChecksum);
+		}
+
+		let mut maze = Maze::with_dimensions(dimensions);
+		maze.algorithm = Algorithm::from_u8(bytes[5]).ok_or(FormatError::UnknownAlgorithm(bytes[5]))?;
+		let mut seed = [0; 8];
+		seed.copy_from_slice(&bytes[6..14]);
+		maze.seed = u64::from_le_bytes(seed);
+		maze.start = Point { x: bytes[14], y: bytes[15] };
+		maze.end = Point { x: bytes[16], y: bytes[17] };
+		if !dimensions.contains(maze.start) || !dimensions.contains(maze.end) {
+			return Err(FormatError::PointOutOfBounds);
+		}
+		let walls = &bytes[HEADER_LEN..len - CHECKSUM_LEN];
+		for (index, location) in dimensions.points().enumerate() {
+			let bits = walls[index / 4] >> ((index % 4) * 2);
+			maze.bitmap_left.set(location, bits & 0b01 != 0);
+			maze.bitmap_top.set(location, bits & 0b10 != 0);
+		}
+		Ok(maze)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algorithms::{Generator, MazeAlgorithm};
+
+	fn generated(algorithm: Algorithm, dimensions: Dimensions) -> Maze {
+		let mut maze = Maze::with_dimensions(dimensions);
+		maze.start = Point { x: 1, y: 1 };
+		Generator::with_seed(algorithm, 0xfeed).generate(&mut maze, || {});
+		maze
+	}
+
+	fn same_maze(a: &Maze, b: &Maze) -> bool {
+		a.dimensions == b.dimensions && a.seed == b.seed && a.algorithm == b.algorithm
+			&& a.start == b.start && a.end == b.end
+			&& a.to_string() == b.to_string()
+	}
+
+	#[test]
+	fn mazes_round_trip() {
+		for &dimensions in &[Dimensions::SMALL, Dimensions::LARGE, Dimensions { width: 5, height: 3 }] {
+			for &algorithm in &Algorithm::ALL {
+				let maze = generated(algorithm, dimensions);
+				let bytes = maze.to_bytes();
+				assert_eq!(bytes.len(), encoded_len(dimensions));
+				let decoded = Maze::from_bytes(&bytes).unwrap();
+				assert!(same_maze(&maze, &decoded), "{:?} {:?}", algorithm, dimensions);
+			}
+		}
+	}
+
+	#[test]
+	fn header_layout_is_stable() {
+		let maze = generated(Algorithm::Kruskal, Dimensions::SMALL);
+		let bytes = maze.to_bytes();
+		assert_eq!(&bytes[..6], &[b'M', b'Z', 1, 8, 4, Algorithm::Kruskal as u8]);
+		assert_eq!(&bytes[6..14], &0xfeed_u64.to_le_bytes());
+		assert_eq!(bytes.len(), 18 + 8 + 2);
+		assert_eq!(MAX_ENCODED_LEN, 18 + 128 + 2);
+	}
+
+	#[test]
+	fn corruption_is_detected() {
+		let maze = generated(Algorithm::Prim, Dimensions::MEDIUM);
+		let bytes = maze.to_bytes();
+		let mut corrupted = [0; MAX_ENCODED_LEN];
+		let corrupted = &mut corrupted[..bytes.len()];
+		corrupted.copy_from_slice(&bytes);
+		corrupted[30] ^= 0x04;
+		assert_eq!(Maze::from_bytes(corrupted).err(), Some(FormatError::BadChecksum));
+		assert_eq!(Maze::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(FormatError::WrongLength));
+		assert_eq!(Maze::from_bytes(&bytes[..4]).err(), Some(FormatError::WrongLength));
+	}
+
+	#[test]
+	fn bad_headers_are_rejected() {
+		let maze = generated(Algorithm::Backtracker, Dimensions::SMALL);
+		let mut bytes = [0; MAX_ENCODED_LEN];
+		let len = maze.to_bytes().len();
+		let edit = |bytes: &mut [u8; MAX_ENCODED_LEN], offset: usize, value: u8| {
+			bytes[..len].copy_from_slice(&maze.to_bytes());
+			bytes[offset] = value;
+			// Fix up the checksum so only the edited field is wrong
+			let checksum = crc16(&bytes[..len - 2]);
+			bytes[len - 2..len].copy_from_slice(&checksum.to_le_bytes());
+		};
+
+		edit(&mut bytes, 0, b'X');
+		assert_eq!(Maze::from_bytes(&bytes[..len]).err(), Some(FormatError::BadMagic));
+		edit(&mut bytes, 2, 9);
+		assert_eq!(Maze::from_bytes(&bytes[..len]).err(), Some(FormatError::UnsupportedVersion(9)));
+		edit(&mut bytes, 3, 1);
+		assert_eq!(Maze::from_bytes(&bytes[..len]).err(), Some(FormatError::InvalidDimensions));
+		edit(&mut bytes, 5, 42);
+		assert_eq!(Maze::from_bytes(&bytes[..len]).err(), Some(FormatError::UnknownAlgorithm(42)));
+		edit(&mut bytes, 16, 8);
+		assert_eq!(Maze::from_bytes(&bytes[..len]).err(), Some(FormatError::PointOutOfBounds));
+	}
+}
@@ -0,0 +1,200 @@
+//! Picture for the LED panel, drawn by the game and shifted out row by row.
+//!
+//! Besides its own pixels, the framebuffer takes anything `embedded-graphics`
+//! draws, in `Rgb888` brought down to the 8 colours of the panel.
+
+use crate::display::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use core::convert::Infallible;
+use embedded_graphics::drawable::Pixel;
+use embedded_graphics::geometry::Size;
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics::DrawTarget;
+
+pub const WIDTH: usize = SCREEN_WIDTH as usize;
+pub const HEIGHT: usize = SCREEN_HEIGHT as usize;
+/// Rows of each half, row `n` of the upper half is lit together with row `n` of the lower one.
+pub const ROWS: usize = HEIGHT / 2;
+
+/// One pixel, red in bit 2, green in bit 1 and blue in bit 0.
+pub type Colour = u8;
+pub const BLACK: Colour = 0b000;
+pub const BLUE: Colour = 0b001;
+pub const GREEN: Colour = 0b010;
+pub const RED: Colour = 0b100;
+pub const WHITE: Colour = 0b111;
+
+/// Nearest colour of the panel, each channel is either fully on or off.
+pub fn quantize(color: Rgb888) -> Colour {
+    let on = |channel: u8| (channel >= 0x80) as u8;
+    on(color.r()) << 2 | on(color.g()) << 1 | on(color.b())
+}
+
+pub fn to_rgb(colour: Colour) -> Rgb888 {
+    let channel = |bit: Colour| if colour & bit != 0 { 0xff } else { 0 };
+    Rgb888::new(channel(RED), channel(GREEN), channel(BLUE))
+}
+
+/// Bytes of a scan row: four columns, with two pixels each, fit in three bytes.
+const ROW_BYTES: usize = WIDTH * 6 / 8;
+
+/// The whole panel, 3K packed in the order it is sent.
+///
+/// Each column of a scan row takes six bits, the pixel of the upper half in
+/// bits 5..3 and the one of the lower half in bits 2..0, exactly what
+/// `HUBPort::next_pixel` takes. There is no room for a second buffer in the 8K
+/// of RAM, so draw small changes at a time and the display task will only ever
+/// show one of them half done.
+pub struct Framebuffer {
+    rows: [[u8; ROW_BYTES]; ROWS],
+}
+
+impl Framebuffer {
+    pub const fn new() -> Framebuffer {
+        Framebuffer { rows: [[0; ROW_BYTES]; ROWS] }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows = [[0; ROW_BYTES]; ROWS];
+    }
+
+    /// Scan row, first byte of the four columns and shift of the pixel at `(x, y)`.
+    fn locate(x: u8, y: u8) -> (usize, usize, u32) {
+        let (row, half) = if (y as usize) < ROWS { (y as usize, 3) } else { (y as usize - ROWS, 0) };
+        (row, x as usize / 4 * 3, (x as u32 % 4) * 6 + half)
+    }
+
+    /// The twelve pixels of four columns starting at `byte`.
+    fn group(row: &[u8; ROW_BYTES], byte: usize) -> u32 {
+        row[byte] as u32 | (row[byte + 1] as u32) << 8 | (row[byte + 2] as u32) << 16
+    }
+
+    pub fn get(&self, x: u8, y: u8) -> Colour {
+        if x as usize >= WIDTH || y as usize >= HEIGHT {
+            return BLACK;
+        }
+        let (row, byte, shift) = Framebuffer::locate(x, y);
+        (Framebuffer::group(&self.rows[row], byte) >> shift & 0b111) as Colour
+    }
+
+    /// Sets the pixel at `(x, y)`, if it is on the panel.
+    pub fn set(&mut self, x: u8, y: u8, colour: Colour) {
+        if x as usize >= WIDTH || y as usize >= HEIGHT {
+            return;
+        }
+        let (row, byte, shift) = Framebuffer::locate(x, y);
+        let row = &mut self.rows[row];
+        let group = Framebuffer::group(row, byte) & !(0b111 << shift) | ((colour & 0b111) as u32) << shift;
+        row[byte .. byte + 3].copy_from_slice(&group.to_le_bytes()[.. 3]);
+    }
+
+    /// The pixels of scan row `row` by column, for `HUBPort::next_pixel`.
+    pub fn row(&self, row: u8) -> impl Iterator<Item = u8> + '_ {
+        let row = &self.rows[row as usize];
+        (0 .. ROW_BYTES).step_by(3).flat_map(move |byte| {
+            let group = Framebuffer::group(row, byte);
+            (0 .. 4).map(move |column| (group >> (column * 6) & 0b11_1111) as u8)
+        })
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+impl DrawTarget<Rgb888> for Framebuffer {
+    type Error = Infallible;
+
+    /// Draws the pixel if it falls on the panel.
+    fn draw_pixel(&mut self, pixel: Pixel<Rgb888>) -> Result<(), Infallible> {
+        let Pixel(point, color) = pixel;
+        if point.x >= 0 && point.x < WIDTH as i32 && point.y >= 0 && point.y < HEIGHT as i32 {
+            self.set(point.x as u8, point.y as u8, quantize(color));
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::fonts::{Font6x8, Text};
+    use embedded_graphics::geometry::Point;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::primitives::{Line, Rectangle};
+    use embedded_graphics::style::{PrimitiveStyle, TextStyle};
+
+    #[test]
+    fn pixels_are_kept_apart() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set(3, 5, RED);
+        framebuffer.set(3, 5 + ROWS as u8, BLUE);
+        framebuffer.set(4, 5, WHITE);
+        assert_eq!(framebuffer.get(3, 5), RED);
+        assert_eq!(framebuffer.get(3, 5 + ROWS as u8), BLUE);
+        assert_eq!(framebuffer.get(4, 5), WHITE);
+        assert_eq!(framebuffer.row(5).nth(3), Some(RED << 3 | BLUE));
+
+        framebuffer.set(3, 5, GREEN);
+        assert_eq!(framebuffer.row(5).nth(3), Some(GREEN << 3 | BLUE));
+        assert_eq!(framebuffer.row(5).nth(4), Some(WHITE << 3));
+        framebuffer.clear();
+        assert!(framebuffer.row(5).all(|pair| pair == 0));
+    }
+
+    #[test]
+    fn pixels_off_the_panel_are_ignored() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set(WIDTH as u8, 0, WHITE);
+        framebuffer.set(0, HEIGHT as u8, WHITE);
+        assert_eq!(framebuffer.get(WIDTH as u8, 0), BLACK);
+        assert!((0..ROWS as u8).all(|row| framebuffer.row(row).all(|pair| pair == 0)));
+    }
+
+    #[test]
+    fn colours_are_quantized() {
+        assert_eq!(quantize(Rgb888::new(0xff, 0x80, 0x7f)), RED | GREEN);
+        assert_eq!(quantize(Rgb888::BLACK), BLACK);
+        assert_eq!(quantize(Rgb888::WHITE), WHITE);
+        for colour in 0 ..= WHITE {
+            assert_eq!(quantize(to_rgb(colour)), colour);
+        }
+    }
+
+    #[test]
+    fn primitives_are_drawn() {
+        let mut framebuffer = Framebuffer::new();
+        Rectangle::new(Point::new(10, 12), Point::new(20, 28))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb888::CYAN, 1))
+            .draw(&mut framebuffer)
+            .unwrap();
+        assert_eq!(framebuffer.get(10, 12), GREEN | BLUE);
+        assert_eq!(framebuffer.get(20, 28), GREEN | BLUE);
+        assert_eq!(framebuffer.get(15, 20), BLACK);
+
+        // Off the panel on both sides
+        let bottom = HEIGHT as i32 - 1;
+        Line::new(Point::new(-5, bottom), Point::new(200, bottom))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb888::RED, 1))
+            .draw(&mut framebuffer)
+            .unwrap();
+        assert!((0 .. WIDTH as u8).all(|x| framebuffer.get(x, bottom as u8) == RED));
+    }
+
+    #[test]
+    fn text_is_drawn() {
+        let mut framebuffer = Framebuffer::new();
+        Text::new("I", Point::new(0, 20))
+            .into_styled(TextStyle::new(Font6x8, Rgb888::YELLOW))
+            .draw(&mut framebuffer)
+            .unwrap();
+        let lit = |y: u8| (0 .. 6).filter(|&x| framebuffer.get(x, y) == RED | GREEN).count();
+        assert!((20 .. 28).map(lit).sum::<usize>() > 4);
+        assert!((0 .. 20).chain(28 .. HEIGHT as u8).all(|y| lit(y) == 0));
+    }
+}
@@ -0,0 +1,650 @@
+//! Flow of the game, from the first maze to the next level and back.
+//!
+//! The firmware turns what happens, a press of the button or a maze being
+//! ready, into an `Event` for `Game::handle`, and does the `Effect` it gets
+//! back. What each event does depends on the `State` the game is in, so a new
+//! mode or screen is a new state here rather than more checks in the
+//! interrupt handlers.
+
+use crate::menu::{Action, Input, Menu};
+
+/// How many times a second `Game::tick` is called.
+pub const TICKS_PER_SECOND: u32 = 100;
+/// Ticks the game stays on a finished level before the next maze is generated.
+pub const CELEBRATION: u32 = TICKS_PER_SECOND;
+/// Ticks a countdown gives for any maze, on top of `COUNTDOWN_PER_CELL`.
+pub const COUNTDOWN_BASE: u32 = 10 * TICKS_PER_SECOND;
+/// Ticks a countdown gives for every cell of the shortest way to the exit.
+pub const COUNTDOWN_PER_CELL: u32 = TICKS_PER_SECOND;
+
+/// How the time counts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The time is only shown, and counts from the start of the maze.
+    Free = 0,
+    /// The time runs from the ball leaving the start to it reaching the exit,
+    /// and the best one on each maze is kept.
+    Timed,
+    /// Same as `Timed`, but the run is over if the exit isn't reached in time.
+    Countdown,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Free, Mode::Timed, Mode::Countdown];
+
+    pub fn from_u8(id: u8) -> Option<Mode> {
+        Mode::ALL.get(id as usize).cloned()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Free => "free",
+            Mode::Timed => "timed",
+            Mode::Countdown => "countdown",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Nothing generated yet.
+    Boot,
+    /// The first maze is up, waiting for the player to start.
+    Attract,
+    /// The maze of an `Effect::Generate` is being generated.
+    Generating,
+    Playing,
+    /// The ball and the time are stopped until the game is resumed.
+    Paused,
+    /// The exit was reached, the next maze comes after `CELEBRATION` ticks.
+    LevelComplete,
+    /// The run ended without reaching the exit, a press starts over from the first level.
+    GameOver,
+    /// The menu is open on the OLED.
+    Menu,
+}
+
+impl State {
+    /// What to tell the player in this state, if anything.
+    pub fn message(self) -> Option<&'static str> {
+        match self {
+            State::Boot | State::Generating => Some("Generating..."),
+            State::Attract => Some("Press to play"),
+            State::Paused => Some("Paused"),
+            State::LevelComplete => Some("Well done!"),
+            State::GameOver => Some("Game over"),
+            State::Playing | State::Menu => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The firmware is up, with `seed` for the first maze.
+    Booted { seed: u64 },
+    /// The maze asked for by the last `Effect::Generate` is drawn. `shortest` is the number
+    /// of moves from its start to its exit, and `best` the best time on it in ticks, if any.
+    Generated { shortest: u16, best: Option<u32> },
+    /// The ball rolled into another cell.
+    Moved,
+    /// A short press of the button, `at_end` if the ball was on the exit.
+    Pressed { at_end: bool },
+    /// The ball rolled onto the exit.
+    ReachedEnd,
+    LongPressed,
+    /// A step of the stick, see `menu::Stick`.
+    Step(Input),
+    /// Pauses, or resumes a paused game.
+    Pause,
+    /// A new maze was asked for outside of the game, with another level or seed if given.
+    NewMaze { level: Option<u8>, seed: Option<u64> },
+    /// The time for the level ran out.
+    TimeUp,
+}
+
+/// A maze to generate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Generate {
+    pub level: u8,
+    /// Seed of the maze, the next one after the current maze's if `None`.
+    pub seed: Option<u64>,
+    /// Whether the maze starts where the current one ends.
+    pub from_end: bool,
+}
+
+/// What the firmware has to do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+    /// Generate and draw a maze, then send `Event::Generated`.
+    Generate(Generate),
+    /// Play the tune for a finished level, and keep `record` as the best time on the maze
+    /// if it beat the last one.
+    Celebrate { record: Option<u32> },
+    /// The press didn't do anything.
+    Beep,
+    /// Do what was picked in the menu, for the actions that don't change the game.
+    Menu(Action),
+}
+
+pub struct Game {
+    state: State,
+    level: u8,
+    menu: Menu,
+    /// Ticks spent in the current state.
+    ticks: u32,
+    /// Ticks spent playing the current maze, since `timing`.
+    playing: u32,
+    mode: Mode,
+    /// Whether `playing` counts, in the timed modes only once the ball has left the start.
+    timing: bool,
+    /// Ticks a countdown gives for the current maze.
+    limit: u32,
+    /// Best time on the current maze, in ticks.
+    best: Option<u32>,
+    /// Cells the ball went into on the current maze.
+    moves: u16,
+    /// Fewest moves from the start of the current maze to its exit.
+    shortest: u16,
+    /// The maze `Generating` is waiting for.
+    request: Generate,
+    /// Where `Generating` goes once the maze is ready.
+    after_generating: State,
+    /// Where closing the menu goes back to.
+    before_menu: State,
+    /// Whether reaching the exit finishes the level, which leaves the button to pause.
+    auto_finish: bool,
+}
+
+impl Game {
+    /// A game starting at `level`.
+    pub const fn new(level: u8) -> Game {
+        Game {
+            state: State::Boot,
+            level,
+            menu: Menu::new(),
+            ticks: 0,
+            playing: 0,
+            mode: Mode::Free,
+            timing: false,
+            limit: 0,
+            best: None,
+            moves: 0,
+            shortest: 0,
+            request: Generate { level, seed: None, from_end: false },
+            after_generating: State::Playing,
+            before_menu: State::Playing,
+            auto_finish: true,
+        }
+    }
+
+    /// Finishes levels as soon as the ball gets to the exit, instead of with a press on it.
+    /// The press then pauses the game.
+    pub fn set_auto_finish(&mut self, auto_finish: bool) {
+        self.auto_finish = auto_finish;
+    }
+
+    /// Changes how the time counts. The clock of the current maze carries on from where it is.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Level of the maze being played, or being generated.
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn menu(&self) -> &Menu {
+        &self.menu
+    }
+
+    /// Ticks spent playing the current maze, not counting pauses and the menu.
+    /// In the timed modes, the time only starts once the ball leaves the start.
+    pub fn playing_ticks(&self) -> u32 {
+        self.playing
+    }
+
+    /// Ticks left to reach the exit, in `Mode::Countdown`.
+    pub fn ticks_left(&self) -> Option<u32> {
+        match self.mode {
+            Mode::Countdown => Some(self.limit.saturating_sub(self.playing)),
+            _ => None,
+        }
+    }
+
+    /// Best time on the current maze in ticks, including one just made.
+    pub fn best_ticks(&self) -> Option<u32> {
+        self.best
+    }
+
+    /// Cells the ball went into on the current maze, however many times it was there before.
+    pub fn moves(&self) -> u16 {
+        self.moves
+    }
+
+    /// Percentage of the moves that were on the shortest way to the exit, once it's reached.
+    pub fn efficiency(&self) -> Option<u8> {
+        if self.state != State::LevelComplete {
+            return None;
+        }
+        let percent = self.shortest as u32 * 100 / core::cmp::max(self.moves, 1) as u32;
+        Some(core::cmp::min(percent, 100) as u8)
+    }
+
+    /// The maze being generated, as the last `Effect::Generate` asked for it.
+    /// For the firmware to start it anyway if that effect got lost.
+    pub fn generating(&self) -> Option<Generate> {
+        match self.state {
+            State::Generating => Some(self.request),
+            _ => None,
+        }
+    }
+
+    /// Whether the stick moves the ball.
+    pub fn ball_moves(&self) -> bool {
+        self.state == State::Playing
+    }
+
+    fn go_to(&mut self, state: State) {
+        self.state = state;
+        self.ticks = 0;
+    }
+
+    fn generate(&mut self, request: Generate, then: State) -> Option<Effect> {
+        self.level = request.level;
+        self.request = request;
+        self.after_generating = then;
+        self.go_to(State::Generating);
+        Some(Effect::Generate(request))
+    }
+
+    fn finish(&mut self) -> Option<Effect> {
+        self.go_to(State::LevelComplete);
+        let record = match self.mode {
+            Mode::Free => None,
+            _ if self.best.is_none_or(|best| self.playing < best) => Some(self.playing),
+            _ => None,
+        };
+        if record.is_some() {
+            self.best = record;
+        }
+        Some(Effect::Celebrate { record })
+    }
+
+    /// Moves the game on, `TICKS_PER_SECOND` times a second.
+    pub fn tick(&mut self) -> Option<Effect> {
+        self.ticks = self.ticks.saturating_add(1);
+        match self.state {
+            State::Playing if self.timing => {
+                self.playing = self.playing.saturating_add(1);
+                if self.mode == Mode::Countdown && self.playing >= self.limit {
+                    return self.handle(Event::TimeUp);
+                }
+                None
+            }
+            State::LevelComplete if self.ticks >= CELEBRATION => {
+                let next = Generate { level: self.level.saturating_add(1), seed: None, from_end: true };
+                self.generate(next, State::Playing)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) -> Option<Effect> {
+        match (self.state, event) {
+            (State::Boot, Event::Booted { seed }) => {
+                self.generate(Generate { level: self.level, seed: Some(seed), from_end: false }, State::Attract)
+            }
+            (State::Generating, Event::Generated { shortest, best }) => {
+                self.playing = 0;
+                self.timing = self.mode == Mode::Free;
+                self.limit = COUNTDOWN_BASE + COUNTDOWN_PER_CELL * shortest as u32;
+                self.best = best;
+                self.moves = 0;
+                self.shortest = shortest;
+                self.go_to(self.after_generating);
+                None
+            }
+            // The maze can't be changed under the generator, nor before the first one
+            (State::Boot, _) | (State::Generating, _) => None,
+            (_, Event::NewMaze { level, seed }) => {
+                self.menu.close();
+                let level = level.unwrap_or(self.level);
+                self.generate(Generate { level, seed, from_end: false }, State::Playing)
+            }
+
+            (State::Attract, Event::Pressed { .. }) | (State::Attract, Event::Step(_)) => {
+                self.go_to(State::Playing);
+                None
+            }
+            (State::Playing, Event::Moved) => {
+                self.timing = true;
+                self.moves = self.moves.saturating_add(1);
+                None
+            }
+            (State::Playing, Event::ReachedEnd) if self.auto_finish => self.finish(),
+            (State::Playing, Event::Pressed { .. }) if self.auto_finish => {
+                self.go_to(State::Paused);
+                None
+            }
+            (State::Playing, Event::Pressed { at_end: true }) => self.finish(),
+            (State::Playing, Event::Pressed { at_end: false }) => Some(Effect::Beep),
+            (State::Playing, Event::TimeUp) => {
+                self.go_to(State::GameOver);
+                None
+            }
+            (State::Playing, Event::Pause) => {
+                self.go_to(State::Paused);
+                None
+            }
+            (State::Paused, Event::Pause) | (State::Paused, Event::Pressed { .. }) => {
+                self.go_to(State::Playing);
+                None
+            }
+            (State::GameOver, Event::Pressed { .. }) => {
+                self.generate(Generate { level: 0, seed: None, from_end: false }, State::Playing)
+            }
+
+            (State::Attract, Event::LongPressed) | (State::Playing, Event::LongPressed) | (State::Paused, Event::LongPressed) => {
+                self.menu.open();
+                self.before_menu = self.state;
+                self.go_to(State::Menu);
+                None
+            }
+            (State::Menu, Event::LongPressed) => {
+                self.menu.close();
+                self.go_to(self.before_menu);
+                None
+            }
+            (State::Menu, Event::Pressed { .. }) => self.pick(Input::Press),
+            (State::Menu, Event::Step(step)) => self.pick(step),
+
+            _ => None,
+        }
+    }
+
+    /// Passes `input` on to the menu.
+    fn pick(&mut self, input: Input) -> Option<Effect> {
+        let action = self.menu.input(input)?;
+        let new_maze = |level| Generate { level, seed: None, from_end: false };
+        match action {
+            Action::Resume => {
+                self.go_to(self.before_menu);
+                None
+            }
+            Action::NewGame => self.generate(new_maze(self.level), State::Playing),
+            Action::Level(level) => self.generate(new_maze(level), State::Playing),
+            action => Some(Effect::Menu(action)),
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated() -> Event {
+        Event::Generated { shortest: 10, best: None }
+    }
+
+    /// A game playing its first maze at `level`, finished with a press on the exit.
+    fn playing(level: u8) -> Game {
+        let mut game = Game::new(level);
+        game.set_auto_finish(false);
+        game.handle(Event::Booted { seed: 1 });
+        game.handle(generated());
+        game.handle(Event::Pressed { at_end: false });
+        assert_eq!(game.state(), State::Playing);
+        game
+    }
+
+    #[test]
+    fn booting_generates_the_resumed_maze_and_waits_for_the_player() {
+        let mut game = Game::new(3);
+        assert_eq!(game.handle(Event::Pressed { at_end: false }), None);
+        assert_eq!(
+            game.handle(Event::Booted { seed: 42 }),
+            Some(Effect::Generate(Generate { level: 3, seed: Some(42), from_end: false })),
+        );
+        assert_eq!(game.state(), State::Generating);
+        assert_eq!(game.generating(), Some(Generate { level: 3, seed: Some(42), from_end: false }));
+        // Nothing gets in the way of the generator
+        assert_eq!(game.handle(Event::LongPressed), None);
+        assert_eq!(game.handle(generated()), None);
+        assert_eq!(game.state(), State::Attract);
+        assert_eq!(game.generating(), None);
+        assert!(!game.ball_moves());
+        // The first press only starts the game
+        assert_eq!(game.handle(Event::Pressed { at_end: false }), None);
+        assert!(game.ball_moves());
+    }
+
+    #[test]
+    fn the_exit_leads_to_the_next_level() {
+        let mut game = playing(3);
+        assert_eq!(game.handle(Event::Pressed { at_end: false }), Some(Effect::Beep));
+        assert_eq!(game.handle(Event::Pressed { at_end: true }), Some(Effect::Celebrate { record: None }));
+        assert_eq!(game.state(), State::LevelComplete);
+        for _ in 1 .. CELEBRATION {
+            assert_eq!(game.tick(), None);
+        }
+        assert_eq!(game.tick(), Some(Effect::Generate(Generate { level: 4, seed: None, from_end: true })));
+        assert_eq!(game.level(), 4);
+        game.handle(generated());
+        assert_eq!(game.state(), State::Playing);
+    }
+
+    #[test]
+    fn reaching_the_exit_can_finish_the_level_on_its_own() {
+        let mut game = playing(1);
+        assert_eq!(game.handle(Event::ReachedEnd), None);
+        assert_eq!(game.state(), State::Playing);
+
+        game.set_auto_finish(true);
+        // The button pauses instead
+        assert_eq!(game.handle(Event::Pressed { at_end: true }), None);
+        assert_eq!(game.state(), State::Paused);
+        assert_eq!(game.handle(Event::ReachedEnd), None);
+        game.handle(Event::Pressed { at_end: true });
+        assert_eq!(game.state(), State::Playing);
+        assert_eq!(game.handle(Event::ReachedEnd), Some(Effect::Celebrate { record: None }));
+        assert_eq!(game.state(), State::LevelComplete);
+    }
+
+    #[test]
+    fn time_only_counts_while_playing() {
+        let mut game = playing(0);
+        for _ in 0 .. 10 {
+            game.tick();
+        }
+        game.handle(Event::Pause);
+        assert!(!game.ball_moves());
+        for _ in 0 .. 10 {
+            game.tick();
+        }
+        assert_eq!(game.playing_ticks(), 10);
+        game.handle(Event::Pressed { at_end: false });
+        game.tick();
+        assert_eq!(game.playing_ticks(), 11);
+        // A new maze starts from zero
+        game.handle(Event::NewMaze { level: None, seed: Some(7) });
+        game.handle(generated());
+        assert_eq!(game.playing_ticks(), 0);
+    }
+
+    #[test]
+    fn timed_mazes_count_from_leaving_the_start_and_keep_the_best() {
+        let mut game = Game::new(0);
+        game.set_mode(Mode::Timed);
+        game.handle(Event::Booted { seed: 1 });
+        game.handle(Event::Generated { shortest: 10, best: Some(50) });
+        game.handle(Event::Pressed { at_end: false });
+        assert_eq!(game.best_ticks(), Some(50));
+        for _ in 0 .. 20 {
+            game.tick();
+        }
+        assert_eq!(game.playing_ticks(), 0);
+        game.handle(Event::Moved);
+        for _ in 0 .. 40 {
+            game.tick();
+        }
+        assert_eq!(game.handle(Event::ReachedEnd), Some(Effect::Celebrate { record: Some(40) }));
+        assert_eq!(game.best_ticks(), Some(40));
+        // The time stops on the exit
+        game.tick();
+        assert_eq!(game.playing_ticks(), 40);
+        assert_eq!(game.ticks_left(), None);
+
+        // A slower run keeps the record
+        game.handle(Event::NewMaze { level: None, seed: Some(1) });
+        game.handle(Event::Generated { shortest: 10, best: Some(40) });
+        game.handle(Event::Moved);
+        for _ in 0 .. 45 {
+            game.tick();
+        }
+        assert_eq!(game.handle(Event::ReachedEnd), Some(Effect::Celebrate { record: None }));
+        assert_eq!(game.best_ticks(), Some(40));
+    }
+
+    #[test]
+    fn moves_are_scored_against_the_shortest_way() {
+        let mut game = playing(0);
+        for _ in 0 .. 8 {
+            game.handle(Event::Moved);
+        }
+        assert_eq!(game.moves(), 8);
+        assert_eq!(game.efficiency(), None);
+        // Moves while paused don't count
+        game.handle(Event::Pause);
+        game.handle(Event::Moved);
+        game.handle(Event::Pause);
+        for _ in 0 .. 4 {
+            game.handle(Event::Moved);
+        }
+        game.handle(Event::Pressed { at_end: true });
+        assert_eq!(game.moves(), 12);
+        // 10 of the 12 moves were needed
+        assert_eq!(game.efficiency(), Some(83));
+
+        game.handle(Event::NewMaze { level: None, seed: None });
+        game.handle(Event::Generated { shortest: 3, best: None });
+        assert_eq!(game.moves(), 0);
+        for _ in 0 .. 3 {
+            game.handle(Event::Moved);
+        }
+        game.handle(Event::Pressed { at_end: true });
+        assert_eq!(game.efficiency(), Some(100));
+    }
+
+    #[test]
+    fn the_countdown_ends_the_run() {
+        let mut game = playing(0);
+        game.set_mode(Mode::Countdown);
+        game.handle(Event::NewMaze { level: None, seed: Some(3) });
+        game.handle(Event::Generated { shortest: 2, best: None });
+        let limit = COUNTDOWN_BASE + 2 * COUNTDOWN_PER_CELL;
+        game.tick();
+        assert_eq!(game.ticks_left(), Some(limit));
+        game.handle(Event::Moved);
+        for _ in 1 .. limit {
+            assert_eq!(game.tick(), None);
+        }
+        assert_eq!(game.ticks_left(), Some(1));
+        // Pausing stops the clock
+        game.handle(Event::Pause);
+        game.tick();
+        game.handle(Event::Pause);
+        assert_eq!(game.state(), State::Playing);
+        game.tick();
+        assert_eq!(game.ticks_left(), Some(0));
+        assert_eq!(game.state(), State::GameOver);
+    }
+
+    #[test]
+    fn running_out_of_time_starts_over() {
+        let mut game = playing(4);
+        game.handle(Event::TimeUp);
+        assert_eq!(game.state(), State::GameOver);
+        assert_eq!(game.handle(Event::Step(Input::Up)), None);
+        assert_eq!(
+            game.handle(Event::Pressed { at_end: false }),
+            Some(Effect::Generate(Generate { level: 0, seed: None, from_end: false })),
+        );
+    }
+
+    #[test]
+    fn the_menu_takes_over_the_stick_and_the_button() {
+        let mut game = playing(2);
+        game.handle(Event::LongPressed);
+        assert_eq!(game.state(), State::Menu);
+        assert!(game.menu().is_open());
+        // Down to the sound, which the firmware takes care of
+        for _ in 0 .. 4 {
+            assert_eq!(game.handle(Event::Step(Input::Down)), None);
+        }
+        assert_eq!(game.handle(Event::Pressed { at_end: true }), Some(Effect::Menu(Action::ToggleSound)));
+        assert_eq!(game.state(), State::Menu);
+        game.handle(Event::LongPressed);
+        assert_eq!(game.state(), State::Playing);
+        assert!(!game.menu().is_open());
+
+        // A new game from the menu goes through the generator
+        game.handle(Event::LongPressed);
+        assert_eq!(
+            game.handle(Event::Pressed { at_end: false }),
+            Some(Effect::Generate(Generate { level: 2, seed: None, from_end: false })),
+        );
+        assert!(!game.menu().is_open());
+        game.handle(generated());
+        assert_eq!(game.state(), State::Playing);
+    }
+
+    #[test]
+    fn closing_the_menu_goes_back_to_where_it_was_opened() {
+        let mut game = playing(1);
+        game.handle(Event::Pause);
+        game.handle(Event::LongPressed);
+        game.handle(Event::LongPressed);
+        assert_eq!(game.state(), State::Paused);
+        for _ in 0 .. 10 {
+            game.tick();
+        }
+        assert_eq!(game.playing_ticks(), 0);
+
+        let mut game = Game::new(0);
+        game.handle(Event::Booted { seed: 1 });
+        game.handle(generated());
+        game.handle(Event::LongPressed);
+        // Left on the main page resumes
+        assert_eq!(game.handle(Event::Step(Input::Left)), None);
+        assert_eq!(game.state(), State::Attract);
+        assert!(!game.menu().is_open());
+    }
+
+    #[test]
+    fn new_mazes_can_be_asked_for_any_time_after_boot() {
+        let mut game = Game::new(0);
+        assert_eq!(game.handle(Event::NewMaze { level: Some(2), seed: None }), None);
+        let mut game = playing(0);
+        game.handle(Event::LongPressed);
+        assert_eq!(
+            game.handle(Event::NewMaze { level: Some(2), seed: Some(9) }),
+            Some(Effect::Generate(Generate { level: 2, seed: Some(9), from_end: false })),
+        );
+        assert!(!game.menu().is_open());
+        assert_eq!(game.level(), 2);
+    }
+}
@@ -0,0 +1,327 @@
+//! The HUB port of the LED panel, and the drivers that scan a panel through it.
+//!
+//! Panels differ in how they pick the row they light. `HUBPort` drives the
+//! panel this game was built on, whose rows are selected by a shift register,
+//! and `HUB75Port` drives standard HUB75 and HUB75E panels, which take the
+//! number of the row on their A to E lines. Both are `PanelDriver`s, so the
+//! rest of the game doesn't care which one it scans.
+
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+
+/// What scanning a panel takes, whatever the way it picks its rows.
+pub trait PanelDriver {
+    /// Shifts the next column in, with the pixel of the upper half in bits
+    /// 5..3 and the one of the lower half in bits 2..0, red first.
+    fn next_pixel(&mut self, pixel: u8);
+
+    /// Latches the columns shifted in and lights them on scan row `row` of
+    /// both halves, until the next call or `blank`.
+    ///
+    /// Rows come in order, each one as many times in a row as needed, with 0
+    /// following the last one.
+    fn show(&mut self, row: u8);
+
+    /// Switches the LEDs off until the next `show`.
+    fn blank(&mut self);
+}
+
+/// Stands in for a line the port doesn't drive itself, such as a clock coming from a timer.
+pub struct Unused;
+
+impl OutputPin for Unused {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Row selection by shift register: A clocks it, C is its data input and B enables its outputs.
+pub struct HUBRowSelectionPort<A, B, C>
+where A: OutputPin,
+      B: OutputPin,
+      C: OutputPin, {
+    pub a: A,
+    pub b: B,
+    pub c: C,
+    /// Row shown last, `None` before the first one.
+    row: Option<u8>,
+}
+
+impl<A, B, C> HUBRowSelectionPort<A, B, C>
+where A: OutputPin,
+      B: OutputPin,
+      C: OutputPin, {
+    pub fn new(a: A, b: B, c: C) -> HUBRowSelectionPort<A, B, C> {
+        HUBRowSelectionPort { a, b, c, row: None }
+    }
+}
+
+/// Row selection by demux, the binary number of the row on A to E.
+///
+/// Panels of 16 or 32 pixels high have fewer lines, leave the missing ones `Unused`.
+pub struct HUBAddressPort<A, B, C, D, E>
+where A: OutputPin,
+      B: OutputPin,
+      C: OutputPin,
+      D: OutputPin,
+      E: OutputPin, {
+    pub a: A,
+    pub b: B,
+    pub c: C,
+    pub d: D,
+    pub e: E,
+}
+
+impl<A, B, C, D, E> HUBAddressPort<A, B, C, D, E>
+where A: OutputPin,
+      B: OutputPin,
+      C: OutputPin,
+      D: OutputPin,
+      E: OutputPin, {
+    fn select(&mut self, row: u8) {
+        set(&mut self.a, row & 0b00001 != 0);
+        set(&mut self.b, row & 0b00010 != 0);
+        set(&mut self.c, row & 0b00100 != 0);
+        set(&mut self.d, row & 0b01000 != 0);
+        set(&mut self.e, row & 0b10000 != 0);
+    }
+}
+
+fn set<P: OutputPin>(pin: &mut P, high: bool) {
+    if high {
+        pin.set_high().ok();
+    } else {
+        pin.set_low().ok();
+    }
+}
+
+pub struct HUBDataPort<R, G, B>
+where R: OutputPin,
+      G: OutputPin,
+      B: OutputPin, {
+    pub r: R,
+    pub g: G,
+    pub b: B,
+}
+
+impl<R, G, B> HUBDataPort<R, G, B>
+where R: OutputPin,
+      G: OutputPin,
+      B: OutputPin, {
+    /// Puts a pixel on the lines, red in bit 2, green in bit 1 and blue in bit 0.
+    fn write(&mut self, pixel: u8) {
+        set(&mut self.r, pixel & 0b100 != 0);
+        set(&mut self.g, pixel & 0b010 != 0);
+        set(&mut self.b, pixel & 0b001 != 0);
+    }
+}
+
+/// Port of the panel in the README, with its rows selected by a shift register.
+pub struct HUBPort<CLK, OEN, LT, A, B, C, R1, G1, B1, R2, G2, B2>
+where CLK: OutputPin,
+      OEN: OutputPin,
+      LT: OutputPin,
+      A: OutputPin,
+      B: OutputPin,
+      C: OutputPin,
+      R1: OutputPin,
+      G1: OutputPin,
+      B1: OutputPin,
+      R2: OutputPin,
+      B2: OutputPin,
+      G2: OutputPin,
+{
+    pub clock: CLK,
+    pub output_enabled: OEN,
+    pub latch: LT,
+    pub row_selection: HUBRowSelectionPort<A, B, C>,
+    pub data_upper: HUBDataPort<R1, G1, B1>,
+    pub data_lower: HUBDataPort<R2, G2, B2>,
+}
+
+impl<CLK, OEN, LT, A, B, C, R1, G1, B1, R2, G2, B2> HUBPort<CLK, OEN, LT, A, B, C, R1, G1, B1, R2, G2, B2>
+    where CLK: OutputPin,
+          OEN: OutputPin,
+          LT: OutputPin,
+          A: OutputPin,
+          B: OutputPin,
+          C: OutputPin,
+          R1: OutputPin,
+          G1: OutputPin,
+          B1: OutputPin,
+          R2: OutputPin,
+          B2: OutputPin,
+          G2: OutputPin,
+ {
+    pub fn next_line(&mut self) {
+        self.row_selection.c.set_low().ok();
+        self.output_enabled.set_high().ok();
+        self.latch.set_high().ok();
+        self.latch.set_low().ok();
+
+        self.row_selection.a.set_high().ok();
+        self.row_selection.a.set_low().ok();
+        self.output_enabled.set_low().ok();
+    }
+     pub fn next_page(&mut self) {
+         self.row_selection.c.set_high().ok();
+         self.output_enabled.set_high().ok();
+         self.latch.set_high().ok();
+         self.latch.set_low().ok();
+
+         self.row_selection.a.set_high().ok();
+         self.row_selection.a.set_low().ok();
+         self.output_enabled.set_low().ok();
+     }
+     pub fn flush(&mut self) {
+         self.output_enabled.set_high().ok();
+         self.latch.set_high().ok();
+         self.latch.set_low().ok();
+         self.output_enabled.set_low().ok();
+     }
+     /// Switches the LEDs off until the next row is shown, to dim the panel.
+     pub fn blank(&mut self) {
+         self.output_enabled.set_high().ok();
+     }
+     pub fn next_pixel(&mut self, pixel: u8) {
+         self.clock.set_high().ok();
+
+         if ((pixel >> 5) & 0b001) == 1 {
+             self.data_upper.r.set_high().ok();
+         } else {
+             self.data_upper.r.set_low().ok();
+         }
+         if ((pixel >> 4) & 0b001) == 1 {
+             self.data_upper.g.set_high().ok();
+         } else {
+             self.data_upper.g.set_low().ok();
+         }
+         if ((pixel >> 3) & 0b001) == 1 {
+             self.data_upper.b.set_high().ok();
+         } else {
+             self.data_upper.b.set_low().ok();
+         }
+         if ((pixel >> 2) & 0b001) == 1 {
+             self.data_lower.r.set_high().ok();
+         } else {
+             self.data_lower.r.set_low().ok();
+         }
+         if ((pixel >> 1) & 0b001) == 1 {
+             self.data_lower.g.set_high().ok();
+         } else {
+             self.data_lower.g.set_low().ok();
+         }
+         if (pixel & 0b001) == 1 {
+             self.data_lower.b.set_high().ok();
+         } else {
+             self.data_lower.b.set_low().ok();
+         }
+         self.clock.set_low().ok();
+     }
+}
+
+
+
+impl<CLK, OEN, LT, A, B, C, R1, G1, B1, R2, G2, B2> PanelDriver for HUBPort<CLK, OEN, LT, A, B, C, R1, G1, B1, R2, G2, B2>
+    where CLK: OutputPin,
+          OEN: OutputPin,
+          LT: OutputPin,
+          A: OutputPin,
+          B: OutputPin,
+          C: OutputPin,
+          R1: OutputPin,
+          G1: OutputPin,
+          B1: OutputPin,
+          R2: OutputPin,
+          B2: OutputPin,
+          G2: OutputPin,
+{
+    fn next_pixel(&mut self, pixel: u8) {
+        HUBPort::next_pixel(self, pixel);
+    }
+
+    /// The shift register can only move on to the next row, or start over
+    /// from row 0, which is all a scan needs.
+    fn show(&mut self, row: u8) {
+        if self.row_selection.row == Some(row) {
+            self.flush();
+        } else if row == 0 {
+            self.next_page();
+        } else {
+            self.next_line();
+        }
+        self.row_selection.row = Some(row);
+    }
+
+    fn blank(&mut self) {
+        HUBPort::blank(self);
+    }
+}
+
+/// Port of a standard HUB75 or HUB75E panel, with its rows selected by a demux.
+pub struct HUB75Port<CLK, OEN, LT, A, B, C, D, E, R1, G1, B1, R2, G2, B2>
+where CLK: OutputPin,
+      OEN: OutputPin,
+      LT: OutputPin,
+      A: OutputPin,
+      B: OutputPin,
+      C: OutputPin,
+      D: OutputPin,
+      E: OutputPin,
+      R1: OutputPin,
+      G1: OutputPin,
+      B1: OutputPin,
+      R2: OutputPin,
+      B2: OutputPin,
+      G2: OutputPin,
+{
+    pub clock: CLK,
+    pub output_enabled: OEN,
+    pub latch: LT,
+    pub address: HUBAddressPort<A, B, C, D, E>,
+    pub data_upper: HUBDataPort<R1, G1, B1>,
+    pub data_lower: HUBDataPort<R2, G2, B2>,
+}
+
+impl<CLK, OEN, LT, A, B, C, D, E, R1, G1, B1, R2, G2, B2> PanelDriver for HUB75Port<CLK, OEN, LT, A, B, C, D, E, R1, G1, B1, R2, G2, B2>
+    where CLK: OutputPin,
+          OEN: OutputPin,
+          LT: OutputPin,
+          A: OutputPin,
+          B: OutputPin,
+          C: OutputPin,
+          D: OutputPin,
+          E: OutputPin,
+          R1: OutputPin,
+          G1: OutputPin,
+          B1: OutputPin,
+          R2: OutputPin,
+          B2: OutputPin,
+          G2: OutputPin,
+{
+    fn next_pixel(&mut self, pixel: u8) {
+        self.clock.set_high().ok();
+        self.data_upper.write(pixel >> 3);
+        self.data_lower.write(pixel);
+        self.clock.set_low().ok();
+    }
+
+    fn show(&mut self, row: u8) {
+        // Dark while the row changes, or the old row flashes the new data
+        self.output_enabled.set_high().ok();
+        self.latch.set_high().ok();
+        self.latch.set_low().ok();
+        self.address.select(row);
+        self.output_enabled.set_low().ok();
+    }
+
+    fn blank(&mut self) {
+        self.output_enabled.set_high().ok();
+    }
+}
@@ -0,0 +1,219 @@
+//! Status of the game on the small OLED next to the panel.
+//!
+//! The OLED is a 128x64 monochrome display on a slow I2C bus, so the firmware
+//! only redraws it when what it shows has changed, from its lowest priority
+//! task. Everything here draws on any `embedded-graphics` target.
+
+use core::fmt::{self, Write};
+use core::str;
+use embedded_graphics::fonts::{Font6x8, Text};
+use embedded_graphics::geometry::Point;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::style::TextStyle;
+use embedded_graphics::DrawTarget;
+
+/// Characters of `Font6x8` that fit across the OLED.
+pub const COLUMNS: usize = 128 / 6;
+/// Lines of `Font6x8` that fit down the OLED.
+pub const LINES: usize = 64 / 8;
+
+/// What the OLED shows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub level: u8,
+    pub seed: u64,
+    pub muted: bool,
+    /// Seconds spent on the current maze.
+    pub elapsed: u32,
+    /// Cells the ball went through, if they are counted.
+    pub moves: Option<u16>,
+    /// Percentage of the moves that were needed, once the exit is reached.
+    pub efficiency: Option<u8>,
+    /// Best time on this maze in seconds, if there is one.
+    pub best: Option<u32>,
+    /// What the game tells the player, under the rest.
+    pub message: Option<&'static str>,
+}
+
+/// A line of text as long as the OLED is wide, anything past it is dropped.
+pub(crate) struct Line {
+    bytes: [u8; COLUMNS],
+    length: usize,
+}
+
+impl Line {
+    pub(crate) fn new() -> Line {
+        Line { bytes: [0; COLUMNS], length: 0 }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // Only whole `str`s are written, and cut on a character boundary
+        str::from_utf8(&self.bytes[.. self.length]).unwrap_or("")
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let mut encoded = [0; 4];
+            let encoded = c.encode_utf8(&mut encoded).as_bytes();
+            if self.length + encoded.len() > COLUMNS {
+                break;
+            }
+            self.bytes[self.length .. self.length + encoded.len()].copy_from_slice(encoded);
+            self.length += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+/// Draws `text` on line `line` of the OLED.
+pub(crate) fn draw_line<D: DrawTarget<BinaryColor>>(target: &mut D, line: usize, text: &str) -> Result<(), D::Error> {
+    Text::new(text, Point::new(0, line as i32 * 8))
+        .into_styled(TextStyle::new(Font6x8, BinaryColor::On))
+        .draw(target)
+}
+
+/// Seconds as `m:ss`.
+struct Time(u32);
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// A line of the status.
+#[derive(Copy, Clone)]
+enum Field {
+    Level,
+    Time,
+    Best(u32),
+    Moves(u16),
+    Efficiency(u8),
+    Seed,
+    Message(&'static str),
+}
+
+impl Status {
+    /// Writes line `line` of the status, returns false past the last one.
+    ///
+    /// Lines for what isn't known are left out, so the rest moves up.
+    pub fn write_line<W: Write>(&self, line: usize, out: &mut W) -> Result<bool, fmt::Error> {
+        let fields = [
+            Some(Field::Level),
+            Some(Field::Time),
+            self.best.map(Field::Best),
+            self.moves.map(Field::Moves),
+            self.efficiency.map(Field::Efficiency),
+            Some(Field::Seed),
+            self.message.map(Field::Message),
+        ];
+        let field = match fields.iter().flatten().nth(line) {
+            Some(&field) => field,
+            None => return Ok(false),
+        };
+        match field {
+            // Pushed to the right edge
+            Field::Level if self.muted => write!(out, "Level {:<10}Muted", self.level)?,
+            Field::Level => write!(out, "Level {}", self.level)?,
+            Field::Time => write!(out, "Time  {}", Time(self.elapsed))?,
+            Field::Best(best) => write!(out, "Best  {}", Time(best))?,
+            Field::Moves(moves) => write!(out, "Moves {}", moves)?,
+            Field::Efficiency(percent) => write!(out, "Efficiency {}%", percent)?,
+            Field::Seed => write!(out, "Seed {:016x}", self.seed)?,
+            Field::Message(message) => out.write_str(message)?,
+        }
+        Ok(true)
+    }
+
+    /// Draws the status from the top left corner, on a target cleared beforehand.
+    pub fn draw<D: DrawTarget<BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        for line in 0 .. LINES {
+            let mut text = Line::new();
+            if !self.write_line(line, &mut text).unwrap_or(false) {
+                break;
+            }
+            draw_line(target, line, text.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_graphics::geometry::Size;
+
+    struct Screen {
+        pixels: [[bool; 128]; 64],
+    }
+
+    impl DrawTarget<BinaryColor> for Screen {
+        type Error = Infallible;
+
+        fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Infallible> {
+            let Pixel(point, colour) = pixel;
+            assert!(point.x >= 0 && point.x < 128 && point.y >= 0 && point.y < 64);
+            self.pixels[point.y as usize][point.x as usize] = colour == BinaryColor::On;
+            Ok(())
+        }
+
+        fn size(&self) -> Size {
+            Size::new(128, 64)
+        }
+    }
+
+    fn status() -> Status {
+        Status { level: 12, seed: 0x1234_5678_9abc_def0, muted: false, elapsed: 83, moves: None, efficiency: None, best: None, message: None }
+    }
+
+    fn lines(status: &Status) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if !status.write_line(lines.len(), &mut line).unwrap() {
+                return lines;
+            }
+            assert!(line.len() <= COLUMNS, "{:?} is too long", line);
+            lines.push(line);
+        }
+    }
+
+    #[test]
+    fn unknown_lines_are_left_out() {
+        assert_eq!(lines(&status()), ["Level 12", "Time  1:23", "Seed 123456789abcdef0"]);
+        let status = Status { muted: true, moves: Some(40), efficiency: Some(85), best: Some(3671), message: Some("Well done!"), ..status() };
+        assert_eq!(lines(&status), [
+            "Level 12        Muted",
+            "Time  1:23",
+            "Best  61:11",
+            "Moves 40",
+            "Efficiency 85%",
+            "Seed 123456789abcdef0",
+            "Well done!",
+        ]);
+    }
+
+    #[test]
+    fn lines_are_cut_to_the_width() {
+        let mut line = Line::new();
+        write!(line, "{}", "x".repeat(30)).unwrap();
+        assert_eq!(line.as_str().len(), COLUMNS);
+        let mut line = Line::new();
+        write!(line, "{}é", "x".repeat(COLUMNS - 1)).unwrap();
+        assert_eq!(line.as_str(), "x".repeat(COLUMNS - 1));
+    }
+
+    #[test]
+    fn status_is_drawn_line_by_line() {
+        let mut screen = Screen { pixels: [[false; 128]; 64] };
+        status().draw(&mut screen).unwrap();
+        let lit = |lines: core::ops::Range<usize>| screen.pixels[lines].iter().flatten().filter(|&&lit| lit).count();
+        assert!(lit(0 .. 8) > 0);
+        assert!(lit(16 .. 24) > 0);
+        assert_eq!(lit(24 .. 64), 0);
+    }
+}
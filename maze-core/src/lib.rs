@@ -0,0 +1,29 @@
+//! Hardware-independent part of the maze game.
+//!
+//! Everything in here only depends on `core`, `embedded-hal` and `embedded-graphics`, so it builds
+//! for the STM32 as well as for the host, where the unit tests run.
+#![cfg_attr(not(test), no_std)]
+
+pub mod algorithms;
+pub mod ball;
+pub mod brightness;
+pub mod cell;
+pub mod console;
+pub mod crc;
+pub mod decoder;
+pub mod difficulty;
+pub mod display;
+pub mod dma;
+pub mod entropy;
+pub mod format;
+pub mod framebuffer;
+pub mod game;
+pub mod hud;
+pub mod hub;
+pub mod maze;
+pub mod menu;
+pub mod palette;
+pub mod records;
+pub mod settings;
+pub mod solver;
+pub mod store;
@@ -0,0 +1,792 @@
+/// Largest maze supported. All the bitmaps are allocated for this size.
+pub const MAX_WIDTH: u8 = 32;
+pub const MAX_HEIGHT: u8 = 16;
+
+extern crate rand;
+use rand::{Rng, SeedableRng};
+use crate::algorithms::{Algorithm, MazeAlgorithm, MazeRng, Step};
+use crate::cell::Cell;
+use crate::display::{PWM_FREQUENCY, SCREEN_HEIGHT, SCREEN_WIDTH};
+use core::fmt;
+
+/// Size of a maze in cells.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dimensions {
+	pub width: u8,
+	pub height: u8,
+}
+impl Dimensions {
+	/// 16 pixels per cell
+	pub const SMALL: Dimensions = Dimensions { width: 8, height: 4 };
+	/// 8 pixels per cell
+	pub const MEDIUM: Dimensions = Dimensions { width: 16, height: 8 };
+	/// 4 pixels per cell, the whole maze memory
+	pub const LARGE: Dimensions = Dimensions { width: MAX_WIDTH, height: MAX_HEIGHT };
+
+	/// Returns `None` unless the maze is at least 2 x 2 and fits in memory.
+	pub fn new(width: u8, height: u8) -> Option<Dimensions> {
+		if width < 2 || height < 2 || width > MAX_WIDTH || height > MAX_HEIGHT {
+			return None;
+		}
+		Some(Dimensions { width, height })
+	}
+	pub fn cells(&self) -> u16 {
+		self.width as u16 * self.height as u16
+	}
+	/// Size of a cell on the panel in pixels, walls included: the largest that fits.
+	pub fn cell_size(&self) -> u8 {
+		core::cmp::min(SCREEN_WIDTH / self.width, SCREEN_HEIGHT / self.height)
+	}
+	pub fn contains(&self, location: Point) -> bool {
+		location.x < self.width && location.y < self.height
+	}
+	/// The closest cell to `location` inside the maze.
+	pub fn clamp(&self, location: Point) -> Point {
+		Point {
+			x: core::cmp::min(location.x, self.width - 1),
+			y: core::cmp::min(location.y, self.height - 1),
+		}
+	}
+	/// The neighbouring cell in `direction`, or `None` at the border of the maze.
+	pub fn neighbour(&self, location: Point, direction: Direction) -> Option<Point> {
+		let inside = match direction {
+			Direction::Right => location.x < self.width - 1,
+			Direction::Left => location.x > 0,
+			Direction::Top => location.y > 0,
+			Direction::Bottom => location.y < self.height - 1,
+		};
+		if inside { Some(location.dir(direction)) } else { None }
+	}
+	/// Every cell, row by row.
+	pub fn points(&self) -> Points {
+		Points { dimensions: *self, next: Some(Point { x: 0, y: 0 }) }
+	}
+	/// The cell after `location` in the order of `points`, row by row.
+	pub fn after(&self, location: Point) -> Option<Point> {
+		if location.x + 1 < self.width {
+			Some(Point { x: location.x + 1, y: location.y })
+		} else if location.y + 1 < self.height {
+			Some(Point { x: 0, y: location.y + 1 })
+		} else {
+			None
+		}
+	}
+}
+
+pub struct Points {
+	dimensions: Dimensions,
+	next: Option<Point>,
+}
+impl Iterator for Points {
+	type Item = Point;
+	fn next(&mut self) -> Option<Self::Item> {
+		let current = self.next?;
+		self.next = self.dimensions.after(current);
+		Some(current)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Point {
+	pub x: u8,
+	pub y: u8,
+}
+impl Point {
+	pub fn dir(&self, direction: Direction) -> Point {
+		match direction {
+			Direction::Right => self.right(),
+			Direction::Left => self.left(),
+			Direction::Top => self.top(),
+			Direction::Bottom => self.bottom(),
+		}
+	}
+	fn right(&self) -> Point {
+		Point{ x: self.x+1, y: self.y }
+	}
+	fn left(&self) -> Point {
+		Point{ x: self.x-1, y: self.y }
+	}
+	fn top(&self) -> Point {
+		Point{ x: self.x, y: self.y-1 }
+	}
+	fn bottom(&self) -> Point {
+		Point{ x: self.x, y: self.y+1 }
+	}
+}
+
+pub struct BitMap {
+	content: [[u8; (MAX_WIDTH/8) as usize]; MAX_HEIGHT as usize]
+}
+impl BitMap {
+	pub fn set_all(&mut self, value: bool) {
+		let value = if value {u8::MAX} else {0};
+		self.content = [[value; (MAX_WIDTH/8) as usize]; MAX_HEIGHT as usize];
+	}
+	pub fn new(value: bool) -> BitMap {
+		let value = if value {u8::MAX} else {0};
+		BitMap {
+			content: [[value; (MAX_WIDTH/8) as usize]; MAX_HEIGHT as usize]
+		}
+	}
+	pub fn get(&self, location: Point) -> bool {
+		let actual_index = location.x >> 3;
+		let index_within_byte = location.x & 0b111;
+		let byte = self.content[location.y as usize][actual_index as usize];
+		(byte >> index_within_byte) & 0b1 == 0b1
+	}
+	pub fn set(&mut self, location: Point, value: bool) {
+		let actual_index = location.x >> 3;
+		let index_within_byte = location.x & 0b111;
+		let val = 1 << index_within_byte;
+		let byte = &mut self.content[location.y as usize][actual_index as usize];
+		if value {
+			*byte |= val;
+		} else {
+			*byte &= !val;
+		}
+	}
+	pub fn iter(&self) -> BitMapIterator<'_> {
+		BitMapIterator{ map: self, row: 0 }
+	}
+	pub fn row_iter(&self, row: u8) -> BitMapRowIterator<'_> {
+		BitMapRowIterator {
+			data: &self.content[row as usize],
+			col: 0,
+			buf: 0,
+			counter: 0
+		}
+	}
+}
+
+/// Directions packed four to a byte, in the same layout as `Direction as u8`.
+pub struct DirectionMap {
+	content: [[u8; (MAX_WIDTH/4) as usize]; MAX_HEIGHT as usize]
+}
+impl DirectionMap {
+	pub fn new() -> DirectionMap {
+		DirectionMap { content: [[0; (MAX_WIDTH/4) as usize]; MAX_HEIGHT as usize] }
+	}
+	pub fn set_all(&mut self, dir: Direction) {
+		let dir = dir as u8;
+		let value = dir | dir << 2 | dir << 4 | dir << 6;
+		self.content = [[value; (MAX_WIDTH/4) as usize]; MAX_HEIGHT as usize];
+	}
+	pub fn get(&self, location: Point) -> Direction {
+		let byte = self.content[location.y as usize][(location.x >> 2) as usize];
+		Direction::from_bits(byte >> ((location.x & 0b11) << 1))
+	}
+	pub fn set(&mut self, location: Point, dir: Direction) {
+		let shift = (location.x & 0b11) << 1;
+		let byte = &mut self.content[location.y as usize][(location.x >> 2) as usize];
+		*byte = (*byte & !(0b11 << shift)) | ((dir as u8) << shift);
+	}
+}
+
+impl Default for DirectionMap {
+	fn default() -> DirectionMap {
+		DirectionMap::new()
+	}
+}
+
+pub struct BitMapIterator<'a> {
+	map: &'a BitMap,
+	row: u8,
+}
+pub struct BitMapRowIterator<'a> {
+	data: &'a [u8; (MAX_WIDTH/8) as usize],
+	col: u8,
+	buf: u8,
+	counter: u8,
+}
+impl<'a> Iterator for  BitMapIterator<'a>   {
+	type Item = BitMapRowIterator<'a>;
+	fn next(&mut self) -> Option<Self::Item> {
+		self.row += 1;
+		if self.row == MAX_HEIGHT {
+			None
+		} else {
+			Some(BitMapRowIterator {
+				data: &self.map.content[self.row as usize],
+				col: 0,
+				buf: 0,
+				counter: 0
+			})
+		}
+	}
+}
+
+impl<'a> Iterator for  BitMapRowIterator<'a>   {
+	type Item = bool;
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.col == MAX_WIDTH/8 {
+			return None;
+		}
+		if self.counter == 0 {
+			self.counter = 8;
+			self.buf = self.data[self.col as usize];
+			self.col += 1;
+		}
+		self.counter -= 1;
+		let digit: bool = self.buf & 0b1 == 1;
+		self.buf >>= 1;
+		Some(digit)
+	}
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+	Left = 0, Right, Top, Bottom
+}
+impl Direction {
+	pub const ALL: [Direction; 4] = [Direction::Left, Direction::Right, Direction::Top, Direction::Bottom];
+
+	pub fn opposite(self) -> Direction {
+		match self {
+			Direction::Left => Direction::Right,
+			Direction::Right => Direction::Left,
+			Direction::Top => Direction::Bottom,
+			Direction::Bottom => Direction::Top,
+		}
+	}
+	/// Inverse of `direction as u8`. Only the lowest two bits are looked at.
+	pub fn from_bits(bits: u8) -> Direction {
+		match bits & 0b11 {
+			0 => Direction::Left,
+			1 => Direction::Right,
+			2 => Direction::Top,
+			_ => Direction::Bottom,
+		}
+	}
+}
+
+pub struct Maze {
+	pub bitmap_left: BitMap,
+	pub bitmap_top: BitMap,
+	pub start: Point,
+	pub end: Point,
+	/// Seed the maze was generated from.
+	pub seed: u64,
+	/// Algorithm the maze was generated with.
+	pub algorithm: Algorithm,
+	/// Only the cells within these are used, the rest of the bitmaps stays walled.
+	pub dimensions: Dimensions,
+}
+impl Maze {
+	pub fn new() -> Maze {
+		Maze::with_dimensions(Dimensions::LARGE)
+	}
+	pub fn with_dimensions(dimensions: Dimensions) -> Maze {
+		Maze {
+			bitmap_top: BitMap::new(true),
+			bitmap_left: BitMap::new(true),
+			start: Point { x: 0, y: 0 },
+			end: Point { x: 0, y: 0 },
+			seed: 0,
+			algorithm: Algorithm::Backtracker,
+			dimensions,
+		}
+	}
+	pub fn cell_size(&self) -> u8 {
+		self.dimensions.cell_size()
+	}
+	/// Width of the maze on the panel, in ball coordinates.
+	pub fn ball_width(&self) -> u16 {
+		self.dimensions.width as u16 * self.cell_size() as u16 * PWM_FREQUENCY as u16
+	}
+	/// Height of the maze on the panel, in ball coordinates.
+	pub fn ball_height(&self) -> u16 {
+		self.dimensions.height as u16 * self.cell_size() as u16 * PWM_FREQUENCY as u16
+	}
+	pub fn break_wall(&mut self, location: Point, dir: Direction) {
+		match dir {
+			Direction::Top => self.bitmap_top.set(location, false),
+			Direction::Left => self.bitmap_left.set(location, false),
+			Direction::Bottom => self.bitmap_top.set(location.bottom(), false),
+			Direction::Right => self.bitmap_left.set(location.right(), false)
+		}
+	}
+	/// Whether the ball can go from `location` to its neighbour in `dir`.
+	/// The border of the grid always counts as a wall.
+	pub fn is_open(&self, location: Point, dir: Direction) -> bool {
+		match dir {
+			Direction::Left => location.x > 0 && !self.bitmap_left.get(location),
+			Direction::Right => location.x < self.dimensions.width - 1 && !self.bitmap_left.get(location.right()),
+			Direction::Top => location.y > 0 && !self.bitmap_top.get(location),
+			Direction::Bottom => location.y < self.dimensions.height - 1 && !self.bitmap_top.get(location.bottom()),
+		}
+	}
+	pub fn grid_iter<'a>(&'a self) -> impl Iterator<Item = impl Iterator<Item = (bool, bool)> + 'a> + 'a {
+		self.bitmap_left.iter().zip(self.bitmap_top.iter())
+			.map(|(left, top)| left.zip(top))
+	}
+	pub fn row_iter(&self) -> impl Iterator<Item = (BitMapRowIterator<'_>, BitMapRowIterator<'_>)> {
+		self.bitmap_left.iter().zip(self.bitmap_top.iter())
+	}
+	pub fn connected(&self, cell1: &Cell, cell2: &Cell) -> bool {
+		let freq: u16 = PWM_FREQUENCY as u16;
+		let size: u16 = self.cell_size() as u16;
+		let point1 = Point{ x: (cell1.position.x / freq / size) as u8, y: (cell1.position.y / freq / size) as u8 };
+		let point2 = Point{ x: (cell2.position.x / freq / size) as u8, y: (cell2.position.y / freq / size) as u8 };
+		if (point1.x as i16 - point2.x as i16).abs() > 1 {
+			return false;
+		}
+		if (point1.y as i16 - point2.y as i16).abs() > 1 {
+			return false;
+		}
+
+
+
+		if point1.y > point2.y {
+			// point1 is on the bottom of point2
+			if self.bitmap_top.get(point1) { return false; }
+			if point1.x != point2.x {
+				return false;
+			}
+		} else if point1.y < point2.y {
+			// point1 is on the top of point2
+			if point1.x != point2.x {
+				return false;
+			}
+			if self.bitmap_top.get(point2) {
+				return false;
+			}
+		}
+
+		if point1.x > point2.x {
+			// point1 is on the right of point 2
+			if self.bitmap_left.get(point1) {
+				return false;
+			}
+			if point1.y != point2.y {
+				return false;
+			}
+		} else if point1.x < point2.x {
+			// point1 is on the left of point 2
+			if point1.y != point2.y {
+				return false;
+			}
+			if self.bitmap_left.get(point2) {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+impl Default for Maze {
+	fn default() -> Maze {
+		Maze::new()
+	}
+}
+
+/// The maze as ASCII art, see `Maze::drawing`.
+pub struct Drawing<'a> {
+	maze: &'a Maze,
+	ball: Option<Point>,
+}
+impl Maze {
+	/// Draws the maze with `+---+` walls, `S` on the start, `E` on the end
+	/// and `o` on the ball if there is one. `Display` draws it without the ball.
+	pub fn drawing(&self, ball: Option<Point>) -> Drawing<'_> {
+		Drawing { maze: self, ball }
+	}
+}
+impl<'a> fmt::Display for Drawing<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let maze = self.maze;
+		let dimensions = maze.dimensions;
+		for y in 0..dimensions.height {
+			for x in 0..dimensions.width {
+				let top = !maze.is_open(Point { x, y }, Direction::Top);
+				f.write_str(if top { "+---" } else { "+   " })?;
+			}
+			f.write_str("+\n")?;
+			for x in 0..dimensions.width {
+				let location = Point { x, y };
+				let left = !maze.is_open(location, Direction::Left);
+				let marker = if self.ball == Some(location) {
+					'o'
+				} else if maze.start == location {
+					'S'
+				} else if maze.end == location {
+					'E'
+				} else {
+					' '
+				};
+				write!(f, "{} {} ", if left { '|' } else { ' ' }, marker)?;
+			}
+			// The border is always closed
+			f.write_str("|\n")?;
+		}
+		for _ in 0..dimensions.width {
+			f.write_str("+---")?;
+		}
+		f.write_str("+\n")
+	}
+}
+impl fmt::Display for Maze {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.drawing(None).fmt(f)
+	}
+}
+
+pub struct MazeGenerator {
+	state: [[u8; (MAX_WIDTH) as usize]; MAX_HEIGHT as usize],
+	visited: BitMap,
+	seed: u64,
+	rng: MazeRng,
+	/// Cell the walk is on, and how far it is from the start along the maze.
+	current: Point,
+	length: u16,
+	/// Length of the walk to `maze.end`, the furthest cell so far.
+	maxlength: u16,
+	/// Chance out of 256 to keep going straight whenever that's possible.
+	/// Higher values give longer corridors and fewer turns.
+	pub straightness: u8,
+}
+impl MazeGenerator {
+	pub fn new() -> MazeGenerator {
+		MazeGenerator::with_seed(crate::algorithms::DEFAULT_SEED)
+	}
+	pub fn with_seed(seed: u64) -> MazeGenerator {
+		MazeGenerator {
+			state: [[0; MAX_WIDTH as usize]; MAX_HEIGHT as usize],
+			visited: BitMap::new(false),
+			seed,
+			rng: MazeRng::seed_from_u64(seed),
+			current: Point { x: 0, y: 0 },
+			length: 0,
+			maxlength: 0,
+			straightness: 0,
+		}
+	}
+
+	// Maze gen algorithm
+	// States: for each cell, there're 8 bits available.
+	// First four bits: incoming edge direction. Up, Down, Left, Right. 0b0000 means this is starting cell
+	// Last four bits: outgoing edge direction. Same definition.
+
+	// When no more ways to go, backtrack by going to the grid incoming edge is pointing to.
+	fn bin_to_dir(bindir: u8) -> Direction {
+		match bindir {
+			0b1000 => Direction::Top,
+			0b0100 => Direction::Bottom,
+			0b0010 => Direction::Left,
+			_ => Direction::Right,
+		}
+	}
+
+	fn bin_dir_opposite(bindir: u8) -> u8 {
+		if bindir & 0b1010 == 0 {
+			// up or left
+			bindir << 1
+		} else {
+			bindir >> 1
+		}
+	}
+
+}
+
+impl MazeAlgorithm for MazeGenerator {
+	fn seed(&self) -> u64 {
+		self.seed
+	}
+	fn set_seed(&mut self, seed: u64) {
+		self.seed = seed;
+	}
+
+	fn start(&mut self, maze: &mut Maze) {
+		self.rng = crate::algorithms::begin(maze, self.seed, Algorithm::Backtracker);
+		self.state = [[0; MAX_WIDTH as usize]; MAX_HEIGHT as usize];
+		self.visited.set_all(false);
+		self.current = maze.start;
+		self.length = 0;
+		self.maxlength = 0;
+	}
+
+	fn step(&mut self, maze: &mut Maze) -> Option<Step> {
+		loop {
+			let current = self.current;
+			let current_state: &mut u8 = &mut self.state[current.y as usize][current.x as usize];
+			let incoming_edges = *current_state >> 4; // first four bits
+			let outgoing_edges = *current_state & 0b1111; // last four bits
+
+			let mut available_edges_to_go = !(incoming_edges | outgoing_edges) & 0b1111;
+
+			if current.x == 0 {
+				available_edges_to_go &= !0b0010; // dont go left
+			} else if current.x == maze.dimensions.width - 1 {
+				available_edges_to_go &= !0b0001; // dont go right
+			}
+			if current.y == 0 {
+				available_edges_to_go &= !0b1000; // dont go up
+			} else if current.y == maze.dimensions.height - 1 {
+				available_edges_to_go &= !0b0100; // dont go down
+			}
+
+			for i in 0 .. 4 {
+				let dir: u8 = 1 << i;
+				if available_edges_to_go & dir == 0 {
+					// This direction was already blocked
+					continue;
+				}
+				let point = current.dir(MazeGenerator::bin_to_dir(dir));
+				if self.visited.get(point) {
+					// The block on this direction was already visited. Skip it.
+					available_edges_to_go &= !dir;
+				}
+			}
+
+			if available_edges_to_go == 0 {
+				// backtrace
+				self.visited.set(current, true);
+				if incoming_edges == 0 {
+					// incoming edges equals 0, meaning this is the starting location
+					return None;
+				}
+				self.current = current.dir(MazeGenerator::bin_to_dir(incoming_edges));
+				self.length -= 1;
+				continue;
+			}
+
+			// determine the direction to go
+			let mut dir_to_go: u8 = 0;
+			if self.straightness > 0 && incoming_edges != 0 {
+				// Only draw for it when biased, so unbiased mazes stay the same for a seed
+				let ahead = MazeGenerator::bin_dir_opposite(incoming_edges);
+				if available_edges_to_go & ahead != 0 && self.rng.gen::<u8>() < self.straightness {
+					dir_to_go = ahead;
+				}
+			}
+			while dir_to_go == 0 {
+				let rand_num: u8 = self.rng.gen();
+				let rand_num: u8 = 1_u8 << (rand_num & 0b11_u8);
+				dir_to_go = available_edges_to_go & rand_num;
+			}
+			let direction = MazeGenerator::bin_to_dir(dir_to_go);
+			maze.break_wall(current, direction);
+			self.visited.set(current, true);
+
+			*current_state |= dir_to_go;
+			self.current = current.dir(direction);
+			self.length += 1; // going forward
+
+			if self.length > self.maxlength {
+				self.maxlength = self.length;
+				maze.end = self.current;
+			}
+
+			let opposite_dir = MazeGenerator::bin_dir_opposite(dir_to_go);
+			self.state[self.current.y as usize][self.current.x as usize] |= opposite_dir << 4; // set the incoming edges
+			return Some(Step { location: current, direction });
+		}
+	}
+}
+
+impl Default for MazeGenerator {
+	fn default() -> MazeGenerator {
+		MazeGenerator::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algorithms::MazeAlgorithm;
+	use crate::ball::Ball;
+
+	fn reachable_from(maze: &Maze, start: Point) -> usize {
+		let mut seen = BitMap::new(false);
+		let mut stack = vec![start];
+		let mut count = 0;
+		seen.set(start, true);
+		while let Some(p) = stack.pop() {
+			count += 1;
+			for &dir in &Direction::ALL {
+				if maze.is_open(p, dir) && !seen.get(p.dir(dir)) {
+					seen.set(p.dir(dir), true);
+					stack.push(p.dir(dir));
+				}
+			}
+		}
+		count
+	}
+
+	fn open_walls(maze: &Maze) -> usize {
+		let mut count = 0;
+		for p in maze.dimensions.points() {
+			if maze.is_open(p, Direction::Right) { count += 1; }
+			if maze.is_open(p, Direction::Bottom) { count += 1; }
+		}
+		count
+	}
+
+	fn generated(start: Point) -> Maze {
+		let mut maze = Maze::new();
+		maze.start = start;
+		MazeGenerator::new().generate(&mut maze, || {});
+		maze
+	}
+
+	#[test]
+	fn bitmap_set_and_get() {
+		let mut map = BitMap::new(false);
+		map.set(Point { x: 9, y: 3 }, true);
+		assert!(map.get(Point { x: 9, y: 3 }));
+		assert!(!map.get(Point { x: 8, y: 3 }));
+		assert!(!map.get(Point { x: 9, y: 4 }));
+		map.set(Point { x: 9, y: 3 }, false);
+		assert!(!map.get(Point { x: 9, y: 3 }));
+	}
+
+	#[test]
+	fn generated_maze_is_perfect() {
+		let maze = generated(Point { x: 0, y: 0 });
+		let cells = MAX_WIDTH as usize * MAX_HEIGHT as usize;
+		assert_eq!(reachable_from(&maze, maze.start), cells);
+		// A spanning tree over every cell has exactly one passage less than cells
+		assert_eq!(open_walls(&maze), cells - 1);
+	}
+
+	#[test]
+	fn generation_from_any_start_covers_the_grid() {
+		let maze = generated(Point { x: 17, y: 9 });
+		assert_eq!(reachable_from(&maze, Point { x: 0, y: 0 }), MAX_WIDTH as usize * MAX_HEIGHT as usize);
+		assert!(maze.end != maze.start);
+	}
+
+	#[test]
+	fn generation_fits_smaller_dimensions() {
+		let mut maze = Maze::with_dimensions(Dimensions::SMALL);
+		maze.start = Point { x: 20, y: 10 };
+		MazeGenerator::new().generate(&mut maze, || {});
+		assert!(Dimensions::SMALL.contains(maze.start));
+		assert!(Dimensions::SMALL.contains(maze.end));
+		assert_eq!(reachable_from(&maze, maze.start), Dimensions::SMALL.cells() as usize);
+		assert_eq!(open_walls(&maze), Dimensions::SMALL.cells() as usize - 1);
+	}
+
+	#[test]
+	fn dimensions_scale_the_cells_to_the_panel() {
+		// Odd sizes get the largest square cells that still fit
+		let odd = Dimensions::new(5, 3).unwrap();
+		for &dimensions in &[Dimensions::SMALL, Dimensions::MEDIUM, Dimensions::LARGE, odd] {
+			let size = dimensions.cell_size();
+			assert!(size >= 2);
+			assert!(dimensions.width * size <= SCREEN_WIDTH && dimensions.height * size <= SCREEN_HEIGHT);
+			let larger = size + 1;
+			assert!(dimensions.width as u16 * larger as u16 > SCREEN_WIDTH as u16
+				|| dimensions.height as u16 * larger as u16 > SCREEN_HEIGHT as u16);
+		}
+		if (SCREEN_WIDTH, SCREEN_HEIGHT) == (128, 64) {
+			assert_eq!(Dimensions::SMALL.cell_size(), 16);
+			assert_eq!(Dimensions::LARGE.cell_size(), 4);
+			assert_eq!(odd.cell_size(), 21);
+		}
+		assert_eq!(Dimensions::new(1, 4), None);
+		assert_eq!(Dimensions::new(MAX_WIDTH + 1, 4), None);
+	}
+
+	#[test]
+	fn dimensions_walk_their_cells() {
+		let dimensions = Dimensions::new(3, 2).unwrap();
+		let mut points = dimensions.points();
+		assert_eq!(points.next(), Some(Point { x: 0, y: 0 }));
+		assert_eq!(points.next(), Some(Point { x: 1, y: 0 }));
+		assert_eq!(points.nth(1), Some(Point { x: 0, y: 1 }));
+		assert_eq!(points.count(), 2);
+		assert_eq!(dimensions.clamp(Point { x: 9, y: 1 }), Point { x: 2, y: 1 });
+		assert_eq!(dimensions.neighbour(Point { x: 2, y: 0 }, Direction::Right), None);
+		assert_eq!(dimensions.neighbour(Point { x: 2, y: 0 }, Direction::Bottom), Some(Point { x: 2, y: 1 }));
+	}
+
+	#[test]
+	fn generation_keeps_the_outer_walls() {
+		let maze = generated(Point { x: 0, y: 0 });
+		for y in 0..MAX_HEIGHT {
+			assert!(maze.bitmap_left.get(Point { x: 0, y }));
+		}
+		for x in 0..MAX_WIDTH {
+			assert!(maze.bitmap_top.get(Point { x, y: 0 }));
+		}
+	}
+
+	#[test]
+	fn generation_is_deterministic() {
+		let a = generated(Point { x: 3, y: 4 });
+		let b = generated(Point { x: 3, y: 4 });
+		assert!(a.bitmap_left.content == b.bitmap_left.content);
+		assert!(a.bitmap_top.content == b.bitmap_top.content);
+		assert!(a.end == b.end);
+	}
+
+	#[test]
+	fn display_draws_walls_and_markers() {
+		let mut maze = Maze::with_dimensions(Dimensions::new(3, 2).unwrap());
+		maze.break_wall(Point { x: 0, y: 0 }, Direction::Right);
+		maze.break_wall(Point { x: 1, y: 0 }, Direction::Bottom);
+		maze.break_wall(Point { x: 1, y: 1 }, Direction::Right);
+		maze.break_wall(Point { x: 2, y: 1 }, Direction::Top);
+		maze.start = Point { x: 0, y: 0 };
+		maze.end = Point { x: 2, y: 0 };
+		assert_eq!(maze.to_string(), "\
++---+---+---+
+| S     | E |
++---+   +   +
+|   |       |
++---+---+---+
+");
+		let drawing = maze.drawing(Some(Point { x: 1, y: 1 })).to_string();
+		assert_eq!(drawing.lines().nth(3), Some("|   | o     |"));
+		// The ball goes on top of the other markers
+		let drawing = maze.drawing(Some(maze.start)).to_string();
+		assert_eq!(drawing.lines().nth(1), Some("| o     | E |"));
+	}
+
+	#[test]
+	fn display_covers_the_whole_maze() {
+		let maze = generated(Point { x: 0, y: 0 });
+		let drawing = maze.to_string();
+		assert_eq!(drawing.lines().count(), MAX_HEIGHT as usize * 2 + 1);
+		assert!(drawing.lines().all(|line| line.len() == MAX_WIDTH as usize * 4 + 1));
+		assert_eq!(drawing.matches('S').count(), 1);
+		assert_eq!(drawing.matches('E').count(), 1);
+	}
+
+	#[test]
+	fn connected_follows_the_walls() {
+		let mut maze = Maze::new();
+		let here = Point { x: 4, y: 4 };
+		let size = maze.cell_size();
+		let cell = |p: Point| Cell::of_ball_point(&Ball::from_point(&p, size), size);
+
+		assert!(!maze.connected(&cell(here), &cell(here.right())));
+		assert!(!maze.connected(&cell(here), &cell(here.bottom())));
+
+		maze.break_wall(here, Direction::Right);
+		maze.break_wall(here, Direction::Bottom);
+		assert!(maze.connected(&cell(here), &cell(here.right())));
+		assert!(maze.connected(&cell(here.right()), &cell(here)));
+		assert!(maze.connected(&cell(here), &cell(here.bottom())));
+		assert!(maze.connected(&cell(here.bottom()), &cell(here)));
+		assert!(!maze.connected(&cell(here), &cell(here.left())));
+		assert!(!maze.connected(&cell(here), &cell(here.top())));
+	}
+
+	#[test]
+	fn connected_rejects_distant_cells() {
+		let mut maze = Maze::new();
+		let here = Point { x: 4, y: 4 };
+		let size = maze.cell_size();
+		let cell = |p: Point| Cell::of_ball_point(&Ball::from_point(&p, size), size);
+		maze.break_wall(here, Direction::Right);
+		maze.break_wall(here.right(), Direction::Bottom);
+		maze.break_wall(here.right(), Direction::Right);
+
+		// Diagonal neighbours are never directly connected
+		assert!(!maze.connected(&cell(here), &cell(here.right().bottom())));
+		// Neither are cells two steps apart, even along an open corridor
+		assert!(!maze.connected(&cell(here), &cell(here.right().right())));
+	}
+}
@@ -0,0 +1,509 @@
+//! Menu on the OLED, to change the settings and start over without the serial console.
+//!
+//! It's driven by the joystick: the stick moves between the items, a short
+//! press picks one, and a long press opens and closes the menu. `Button` and
+//! `Stick` turn the raw joystick into those `Input`s, `Menu` keeps track of
+//! where the player is and tells the firmware what to do as an `Action`.
+
+use crate::algorithms::Algorithm;
+use crate::brightness::MAX_BRIGHTNESS;
+use crate::difficulty::Difficulty;
+use crate::hud::{draw_line, Line, LINES};
+use crate::maze::Dimensions;
+use core::fmt::{self, Write};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::DrawTarget;
+
+/// Updates the button has to stay down for a long press.
+pub const LONG_PRESS: u16 = 80;
+/// How far the stick has to be pushed to move through the menu, in the units `Stick::update` takes.
+const DEFLECTION: i16 = 8;
+/// Updates the stick has to be held before it starts repeating, and between repeats.
+const REPEAT_DELAY: u16 = 40;
+const REPEAT_EVERY: u16 = 12;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// A short press of the button.
+    Press,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Press {
+    /// Reported once the button is let go.
+    Short,
+    /// Reported as soon as it has been held for `LONG_PRESS` updates, and not again on release.
+    Long,
+}
+
+/// Tells short presses of a button from long ones, from its state at a steady rate.
+pub struct Button {
+    /// Updates it has been down for.
+    held: u16,
+}
+
+impl Button {
+    pub const fn new() -> Button {
+        Button { held: 0 }
+    }
+
+    pub fn update(&mut self, pressed: bool) -> Option<Press> {
+        if pressed {
+            self.held = self.held.saturating_add(1);
+            if self.held == LONG_PRESS {
+                return Some(Press::Long);
+            }
+        } else {
+            let held = self.held;
+            self.held = 0;
+            if held > 0 && held < LONG_PRESS {
+                return Some(Press::Short);
+            }
+        }
+        None
+    }
+}
+
+impl Default for Button {
+    fn default() -> Button {
+        Button::new()
+    }
+}
+
+/// Turns the position of the stick, read at a steady rate, into steps through the menu.
+///
+/// Pushing the stick gives one step, holding it there repeats it after a while.
+pub struct Stick {
+    direction: Option<Input>,
+    /// Updates the stick has been held in `direction` for.
+    held: u16,
+}
+
+impl Stick {
+    pub const fn new() -> Stick {
+        Stick { direction: None, held: 0 }
+    }
+
+    /// Takes how far the stick is pushed right and down, in the units the ball moves by.
+    pub fn update(&mut self, x: i16, y: i16) -> Option<Input> {
+        let direction = if x.abs() < DEFLECTION && y.abs() < DEFLECTION {
+            None
+        } else if x.abs() > y.abs() {
+            Some(if x > 0 { Input::Right } else { Input::Left })
+        } else {
+            Some(if y > 0 { Input::Down } else { Input::Up })
+        };
+        if direction != self.direction {
+            self.direction = direction;
+            self.held = 0;
+            return direction;
+        }
+        self.held = self.held.saturating_add(1);
+        if self.held >= REPEAT_DELAY && (self.held - REPEAT_DELAY) % REPEAT_EVERY == REPEAT_EVERY - 1 {
+            return direction;
+        }
+        None
+    }
+}
+
+impl Default for Stick {
+    fn default() -> Stick {
+        Stick::new()
+    }
+}
+
+/// What the firmware has to do for the player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The menu was closed, the game goes on.
+    Resume,
+    /// A new maze at the current level.
+    NewGame,
+    /// A new maze at `level`, which the game carries on from.
+    Level(u8),
+    /// Generate the next mazes with another algorithm.
+    Algorithm(Algorithm),
+    Brighter,
+    Dimmer,
+    ToggleSound,
+    /// Take the current position of the stick as its centre.
+    Calibrate,
+}
+
+/// What the menu shows besides its items, as the game currently has it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Values {
+    pub level: u8,
+    pub algorithm: Algorithm,
+    pub brightness: u8,
+    pub muted: bool,
+    pub dimensions: Dimensions,
+    pub seed: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Page {
+    Main,
+    Difficulty,
+    Algorithm,
+    Stats,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Item {
+    NewGame,
+    Difficulty,
+    Algorithm,
+    Brightness,
+    Sound,
+    Calibrate,
+    Stats,
+}
+
+const ITEMS: [Item; 7] = [
+    Item::NewGame,
+    Item::Difficulty,
+    Item::Algorithm,
+    Item::Brightness,
+    Item::Sound,
+    Item::Calibrate,
+    Item::Stats,
+];
+
+/// The difficulties to pick from, each starting at the first level that has it.
+const DIFFICULTIES: [(&str, Difficulty); 3] = [
+    ("Easy", Difficulty::EASY),
+    ("Medium", Difficulty::MEDIUM),
+    ("Hard", Difficulty::HARD),
+];
+
+fn first_level(difficulty: Difficulty) -> u8 {
+    Difficulty::LEVELS.iter().position(|&level| level == difficulty).unwrap_or(0) as u8
+}
+
+/// Where the player is in the menu, if it's open.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Menu {
+    open: bool,
+    page: Page,
+    /// Line picked on the page.
+    cursor: u8,
+}
+
+impl Menu {
+    pub const fn new() -> Menu {
+        Menu { open: false, page: Page::Main, cursor: 0 }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Opens the menu on its first item.
+    pub fn open(&mut self) {
+        *self = Menu { open: true, ..Menu::new() };
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    /// Lines the cursor goes through on the current page.
+    fn lines(&self) -> u8 {
+        match self.page {
+            Page::Main => ITEMS.len() as u8,
+            Page::Difficulty => DIFFICULTIES.len() as u8,
+            Page::Algorithm => Algorithm::ALL.len() as u8,
+            Page::Stats => 1,
+        }
+    }
+
+    fn go_to(&mut self, page: Page) {
+        self.page = page;
+        self.cursor = 0;
+    }
+
+    /// Moves through the menu, and returns what the firmware has to do for it.
+    pub fn input(&mut self, input: Input) -> Option<Action> {
+        if !self.open {
+            return None;
+        }
+        let lines = self.lines();
+        match (self.page, input) {
+            (_, Input::Up) => self.cursor = (self.cursor + lines - 1) % lines,
+            (_, Input::Down) => self.cursor = (self.cursor + 1) % lines,
+            (Page::Main, _) => return self.main(input),
+            (_, Input::Left) => self.go_to(Page::Main),
+            (Page::Difficulty, Input::Press) => {
+                self.close();
+                return Some(Action::Level(first_level(DIFFICULTIES[self.cursor as usize].1)));
+            }
+            (Page::Algorithm, Input::Press) => {
+                let algorithm = Algorithm::ALL[self.cursor as usize];
+                self.go_to(Page::Main);
+                return Some(Action::Algorithm(algorithm));
+            }
+            (Page::Stats, Input::Press) => self.go_to(Page::Main),
+            (_, Input::Right) => {}
+        }
+        None
+    }
+
+    /// Left, right or a press on an item of the main page.
+    fn main(&mut self, input: Input) -> Option<Action> {
+        let item = ITEMS[self.cursor as usize];
+        match (item, input) {
+            (Item::Brightness, Input::Left) => Some(Action::Dimmer),
+            (Item::Brightness, Input::Right) => Some(Action::Brighter),
+            (Item::Sound, Input::Press | Input::Right) => Some(Action::ToggleSound),
+            (_, Input::Left) => {
+                self.close();
+                Some(Action::Resume)
+            }
+            (Item::NewGame, Input::Press) => {
+                self.close();
+                Some(Action::NewGame)
+            }
+            (Item::Difficulty, Input::Press) => {
+                self.go_to(Page::Difficulty);
+                None
+            }
+            (Item::Algorithm, Input::Press) => {
+                self.go_to(Page::Algorithm);
+                None
+            }
+            (Item::Calibrate, Input::Press) => Some(Action::Calibrate),
+            (Item::Stats, Input::Press) => {
+                self.go_to(Page::Stats);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes line `line` of the menu, its title first. Returns false past the last one.
+    pub fn write_line<W: Write>(&self, values: &Values, line: usize, out: &mut W) -> Result<bool, fmt::Error> {
+        let index = match line.checked_sub(1) {
+            Some(index) => index,
+            None => {
+                out.write_str(match self.page {
+                    Page::Main => "Menu",
+                    Page::Difficulty => "Difficulty",
+                    Page::Algorithm => "Algorithm",
+                    Page::Stats => "Stats",
+                })?;
+                return Ok(true);
+            }
+        };
+        let cursor = if index == self.cursor as usize { '>' } else { ' ' };
+        match self.page {
+            Page::Main => {
+                let item = match ITEMS.get(index) {
+                    Some(&item) => item,
+                    None => return Ok(false),
+                };
+                write!(out, "{} ", cursor)?;
+                match item {
+                    Item::NewGame => write!(out, "New game"),
+                    Item::Difficulty => write!(out, "Difficulty"),
+                    Item::Algorithm => write!(out, "Algorithm"),
+                    Item::Brightness => write!(out, "Brightness {:>3}/{}", values.brightness, MAX_BRIGHTNESS),
+                    Item::Sound => write!(out, "Sound {:>12}", if values.muted { "off" } else { "on" }),
+                    Item::Calibrate => write!(out, "Calibrate"),
+                    Item::Stats => write!(out, "Stats"),
+                }?;
+            }
+            Page::Difficulty => {
+                let (name, difficulty) = match DIFFICULTIES.get(index) {
+                    Some(&entry) => entry,
+                    None => return Ok(false),
+                };
+                let current = Difficulty::for_level(values.level) == difficulty;
+                write!(out, "{} {}{}", cursor, name, if current { " *" } else { "" })?;
+            }
+            Page::Algorithm => {
+                let algorithm = match Algorithm::ALL.get(index) {
+                    Some(&algorithm) => algorithm,
+                    None => return Ok(false),
+                };
+                let current = values.algorithm == algorithm;
+                write!(out, "{} {}{}", cursor, algorithm.name(), if current { " *" } else { "" })?;
+            }
+            Page::Stats => match index {
+                0 => write!(out, "Level {}", values.level)?,
+                1 => write!(out, "Size {}x{}", values.dimensions.width, values.dimensions.height)?,
+                2 => write!(out, "{}", values.algorithm.name())?,
+                3 => write!(out, "Seed {:016x}", values.seed)?,
+                _ => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+
+    /// Draws the menu on a target cleared beforehand.
+    pub fn draw<D: DrawTarget<BinaryColor>>(&self, values: &Values, target: &mut D) -> Result<(), D::Error> {
+        for line in 0 .. LINES {
+            let mut text = Line::new();
+            if !self.write_line(values, line, &mut text).unwrap_or(false) {
+                break;
+            }
+            draw_line(target, line, text.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Menu {
+        Menu::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_graphics::drawable::Pixel;
+    use embedded_graphics::geometry::Size;
+
+    /// Counts the pixels lit on each line of text.
+    struct Lines([usize; 8]);
+
+    impl DrawTarget<BinaryColor> for Lines {
+        type Error = Infallible;
+
+        fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Infallible> {
+            let Pixel(point, colour) = pixel;
+            assert!(point.x >= 0 && point.x < 128 && point.y >= 0 && point.y < 64);
+            self.0[point.y as usize / 8] += (colour == BinaryColor::On) as usize;
+            Ok(())
+        }
+
+        fn size(&self) -> Size {
+            Size::new(128, 64)
+        }
+    }
+
+    fn opened() -> Menu {
+        let mut menu = Menu::new();
+        menu.open();
+        menu
+    }
+
+    /// Presses the button on item `index` of the main page.
+    fn pick(menu: &mut Menu, index: usize) -> Option<Action> {
+        for _ in 0 .. index {
+            menu.input(Input::Down);
+        }
+        menu.input(Input::Press)
+    }
+
+    #[test]
+    fn short_and_long_presses() {
+        let mut button = Button::new();
+        assert_eq!(button.update(false), None);
+        assert_eq!(button.update(true), None);
+        assert_eq!(button.update(false), Some(Press::Short));
+
+        let presses: Vec<Option<Press>> = (0 .. LONG_PRESS + 10).map(|_| button.update(true)).collect();
+        assert_eq!(presses.iter().filter(|press| press.is_some()).count(), 1);
+        assert_eq!(presses[LONG_PRESS as usize - 1], Some(Press::Long));
+        // Letting go after a long press isn't a short one too
+        assert_eq!(button.update(false), None);
+    }
+
+    #[test]
+    fn the_stick_steps_then_repeats() {
+        let mut stick = Stick::new();
+        assert_eq!(stick.update(2, -3), None);
+        assert_eq!(stick.update(0, 12), Some(Input::Down));
+        let repeats = (0 .. REPEAT_DELAY + 2 * REPEAT_EVERY).filter(|_| stick.update(0, 12).is_some()).count();
+        assert_eq!(repeats, 2);
+        assert_eq!(stick.update(-12, 3), Some(Input::Left));
+        assert_eq!(stick.update(0, 0), None);
+        assert_eq!(stick.update(0, -12), Some(Input::Up));
+    }
+
+    #[test]
+    fn a_closed_menu_ignores_input() {
+        let mut menu = Menu::new();
+        assert_eq!(menu.input(Input::Press), None);
+        assert_eq!(menu, Menu::new());
+    }
+
+    #[test]
+    fn items_do_what_they_say() {
+        let mut menu = opened();
+        assert_eq!(pick(&mut menu, 0), Some(Action::NewGame));
+        assert!(!menu.is_open());
+
+        let mut menu = opened();
+        assert_eq!(pick(&mut menu, 5), Some(Action::Calibrate));
+        assert!(menu.is_open());
+
+        let mut menu = opened();
+        for _ in 0 .. 3 {
+            menu.input(Input::Down);
+        }
+        assert_eq!(menu.input(Input::Right), Some(Action::Brighter));
+        assert_eq!(menu.input(Input::Left), Some(Action::Dimmer));
+        assert!(menu.is_open());
+        menu.input(Input::Down);
+        assert_eq!(menu.input(Input::Press), Some(Action::ToggleSound));
+        assert_eq!(menu.input(Input::Right), Some(Action::ToggleSound));
+        assert_eq!(menu.input(Input::Left), Some(Action::Resume));
+        assert!(!menu.is_open());
+
+        // Up from the top wraps around to the last item
+        let mut menu = opened();
+        menu.input(Input::Up);
+        assert_eq!(menu.input(Input::Press), None);
+        assert_eq!(menu.page, Page::Stats);
+        assert_eq!(menu.input(Input::Left), None);
+        assert_eq!(menu.page, Page::Main);
+        assert_eq!(menu.input(Input::Left), Some(Action::Resume));
+        assert!(!menu.is_open());
+    }
+
+    #[test]
+    fn pages_pick_a_difficulty_or_an_algorithm() {
+        let mut menu = opened();
+        pick(&mut menu, 1);
+        assert_eq!(pick(&mut menu, 2), Some(Action::Level(first_level(Difficulty::HARD))));
+        assert_eq!(Difficulty::for_level(first_level(Difficulty::HARD)), Difficulty::HARD);
+        assert!(!menu.is_open());
+
+        let mut menu = opened();
+        pick(&mut menu, 2);
+        assert_eq!(pick(&mut menu, 3), Some(Action::Algorithm(Algorithm::ALL[3])));
+        // Back on the main page, to pick something else
+        assert!(menu.is_open());
+        assert_eq!(menu.page, Page::Main);
+    }
+
+    #[test]
+    fn every_page_fits_the_oled() {
+        let values = Values {
+            level: 3,
+            algorithm: Algorithm::Wilson,
+            brightness: 5,
+            muted: true,
+            dimensions: Dimensions::MEDIUM,
+            seed: 0x1234,
+        };
+        for &(item, lines) in &[(None, 8), (Some(1), 4), (Some(2), 8), (Some(6), 5)] {
+            let mut menu = opened();
+            if let Some(item) = item {
+                pick(&mut menu, item);
+            }
+            let mut drawn = Lines([0; 8]);
+            menu.draw(&values, &mut drawn).unwrap();
+            assert!(drawn.0[.. lines].iter().all(|&lit| lit > 0), "{:?}", menu);
+            assert!(drawn.0[lines ..].iter().all(|&lit| lit == 0), "{:?}", menu);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Brightness levels of the colours in the framebuffer.
+//!
+//! The panel's drivers can only switch an LED on or off, so levels are shown by
+//! binary code modulation: each row is sent once per bit of the level, and bit
+//! `n` stays lit for `1 << n` units of time. The framebuffer only holds 3 bits
+//! a pixel, which pick one of the 8 colours of a `Palette`.
+
+use crate::framebuffer::Colour;
+
+/// Bits of brightness per channel.
+pub const COLOUR_BITS: u8 = 4;
+pub const MAX_LEVEL: u8 = (1 << COLOUR_BITS) - 1;
+/// Number of values a byte of the framebuffer can take, two 3 bit colours.
+const PIXEL_PAIRS: usize = 64;
+
+/// Brightness of each channel, from 0 to `MAX_LEVEL`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Levels {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Levels {
+    pub const fn new(r: u8, g: u8, b: u8) -> Levels {
+        Levels { r, g, b }
+    }
+
+    /// Levels for a colour given as perceived brightness, 0 to 255 a channel.
+    ///
+    /// LEDs look much brighter than their duty cycle at the low end, so this
+    /// applies a gamma of 2, close enough to the usual 2.2 at 4 bits.
+    pub const fn shade(r: u8, g: u8, b: u8) -> Levels {
+        Levels { r: gamma(r), g: gamma(g), b: gamma(b) }
+    }
+}
+
+const fn gamma(brightness: u8) -> u8 {
+    let squared = brightness as u32 * brightness as u32;
+    ((squared * MAX_LEVEL as u32 + 255 * 255 / 2) / (255 * 255)) as u8
+}
+
+/// The 8 colours a framebuffer can use, ready to be sent.
+pub struct Palette {
+    colours: [Levels; 8],
+    /// For each bit of the levels, what `HUBPort::next_pixel` gets for each byte of the framebuffer.
+    planes: [[u8; PIXEL_PAIRS]; COLOUR_BITS as usize],
+}
+
+impl Palette {
+    /// The colours named in `framebuffer` at full brightness: a bit for each channel.
+    pub const PRIMARIES: Palette = Palette::new([
+        Levels::new(0, 0, 0),
+        Levels::new(0, 0, MAX_LEVEL),
+        Levels::new(0, MAX_LEVEL, 0),
+        Levels::new(0, MAX_LEVEL, MAX_LEVEL),
+        Levels::new(MAX_LEVEL, 0, 0),
+        Levels::new(MAX_LEVEL, 0, MAX_LEVEL),
+        Levels::new(MAX_LEVEL, MAX_LEVEL, 0),
+        Levels::new(MAX_LEVEL, MAX_LEVEL, MAX_LEVEL),
+    ]);
+
+    pub const fn new(colours: [Levels; 8]) -> Palette {
+        let mut planes = [[0; PIXEL_PAIRS]; COLOUR_BITS as usize];
+        let mut plane = 0;
+        while plane < COLOUR_BITS as usize {
+            let mut pair = 0;
+            while pair < PIXEL_PAIRS {
+                let upper = colours[pair >> 3];
+                let lower = colours[pair & 0b111];
+                planes[plane][pair] = (upper.r >> plane & 1) << 5
+                    | (upper.g >> plane & 1) << 4
+                    | (upper.b >> plane & 1) << 3
+                    | (lower.r >> plane & 1) << 2
+                    | (lower.g >> plane & 1) << 1
+                    | (lower.b >> plane & 1);
+                pair += 1;
+            }
+            plane += 1;
+        }
+        Palette { colours, planes }
+    }
+
+    pub fn levels(&self, colour: Colour) -> Levels {
+        self.colours[(colour & 0b111) as usize]
+    }
+
+    /// What to send for a byte of the framebuffer while showing bit `plane` of the levels.
+    pub const fn plane(&self, plane: u8, pair: u8) -> u8 {
+        self.planes[plane as usize][(pair & 0b11_1111) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::{BLUE, GREEN, RED, WHITE};
+
+    #[test]
+    fn gamma_keeps_the_ends() {
+        assert_eq!(Levels::shade(0, 255, 128), Levels::new(0, MAX_LEVEL, 4));
+        let levels: Vec<u8> = (0 ..= 255).map(gamma).collect();
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn planes_hold_the_bits_of_the_levels() {
+        let palette = Palette::new([
+            Levels::new(0, 0, 0),
+            Levels::new(0b0001, 0b0010, 0b0100),
+            Levels::new(0b1000, 0, 0),
+            Levels::default(),
+            Levels::default(),
+            Levels::default(),
+            Levels::default(),
+            Levels::new(MAX_LEVEL, MAX_LEVEL, MAX_LEVEL),
+        ]);
+        let pair = |upper: u8, lower: u8| upper << 3 | lower;
+        assert_eq!(palette.plane(0, pair(1, 7)), 0b100_111);
+        assert_eq!(palette.plane(1, pair(1, 0)), 0b010_000);
+        assert_eq!(palette.plane(2, pair(2, 1)), 0b000_001);
+        assert_eq!(palette.plane(3, pair(2, 1)), 0b100_000);
+        assert_eq!(palette.plane(3, pair(0, 0)), 0);
+    }
+
+    #[test]
+    fn primaries_match_the_framebuffer_bits() {
+        for plane in 0 .. COLOUR_BITS {
+            for &colour in &[RED, GREEN, BLUE, WHITE] {
+                assert_eq!(Palette::PRIMARIES.plane(plane, colour << 3 | colour), colour << 3 | colour);
+            }
+        }
+        assert_eq!(Palette::PRIMARIES.levels(RED), Levels::new(MAX_LEVEL, 0, 0));
+    }
+}
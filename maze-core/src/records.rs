@@ -0,0 +1,124 @@
+//! Best times of the timed modes, kept in the flash `Store` next to the settings.
+//!
+//! A time only compares with others on the same maze, which is its seed, its level and so
+//! its difficulty, the algorithm that carved it, and where it starts. The store holds the
+//! best times of the `RECORDS` mazes last beaten, the latest first, each under a key of its
+//! own so that beating the latest one again only writes that one.
+
+use crate::algorithms::Algorithm;
+use crate::maze::{Maze, Point};
+use crate::store::{Flash, Store, StoreError};
+
+/// How many mazes the best times are kept for.
+pub const RECORDS: u8 = 8;
+/// Key of the latest record, the older ones follow it. The settings take the keys below.
+const FIRST_KEY: u8 = 0x40;
+/// Bytes of a record: seed, level, algorithm, start and ticks.
+const RECORD_LEN: usize = 16;
+
+/// The maze a time was made on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MazeId {
+    pub seed: u64,
+    pub level: u8,
+    pub algorithm: Algorithm,
+    pub start: Point,
+}
+
+impl MazeId {
+    /// The id of `maze`, generated for `level` with `algorithm`.
+    pub fn of(maze: &Maze, level: u8, algorithm: Algorithm) -> MazeId {
+        MazeId { seed: maze.seed, level, algorithm, start: maze.start }
+    }
+
+    fn to_bytes(self, ticks: u32) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[.. 8].copy_from_slice(&self.seed.to_le_bytes());
+        bytes[8] = self.level;
+        bytes[9] = self.algorithm as u8;
+        bytes[10] = self.start.x;
+        bytes[11] = self.start.y;
+        bytes[12 ..].copy_from_slice(&ticks.to_le_bytes());
+        bytes
+    }
+
+    fn matches(self, bytes: &[u8; RECORD_LEN]) -> bool {
+        bytes[.. 12] == self.to_bytes(0)[.. 12]
+    }
+}
+
+/// Reads the record in `slot`, 0 being the latest.
+fn read<F: Flash>(store: &Store<F>, slot: u8) -> Option<[u8; RECORD_LEN]> {
+    let mut bytes = [0; RECORD_LEN];
+    match store.get(FIRST_KEY + slot, &mut bytes) {
+        Some(RECORD_LEN) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Slot of the record of `maze`, if there is one.
+fn find<F: Flash>(store: &Store<F>, maze: MazeId) -> Option<(u8, [u8; RECORD_LEN])> {
+    (0 .. RECORDS).find_map(|slot| {
+        read(store, slot).filter(|bytes| maze.matches(bytes)).map(|bytes| (slot, bytes))
+    })
+}
+
+/// Best time on `maze` in ticks, if it was ever finished in a timed mode.
+pub fn best<F: Flash>(store: &Store<F>, maze: MazeId) -> Option<u32> {
+    let (_, bytes) = find(store, maze)?;
+    Some(u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]))
+}
+
+/// Keeps `ticks` as the best time on `maze`, whether it's better or not, and makes it the latest.
+/// Without room for it, the record of the maze beaten the longest ago is forgotten.
+pub fn keep<F: Flash>(store: &mut Store<F>, maze: MazeId, ticks: u32) -> Result<(), StoreError<F::Error>> {
+    let last = find(store, maze).map_or(RECORDS - 1, |(slot, _)| slot);
+    for slot in (0 .. last).rev() {
+        if let Some(bytes) = read(store, slot) {
+            store.set(FIRST_KEY + slot + 1, &bytes)?;
+        }
+    }
+    store.set(FIRST_KEY, &maze.to_bytes(ticks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::MockFlash;
+
+    fn maze(seed: u64) -> MazeId {
+        MazeId { seed, level: 2, algorithm: Algorithm::Prim, start: Point { x: 3, y: 4 } }
+    }
+
+    #[test]
+    fn records_are_per_maze() {
+        let mut store = Store::mount(MockFlash::new(1024, 2));
+        assert_eq!(best(&store, maze(1)), None);
+        keep(&mut store, maze(1), 500).unwrap();
+        keep(&mut store, maze(2), 700).unwrap();
+        keep(&mut store, maze(1), 450).unwrap();
+        let store = Store::mount(store.release());
+        assert_eq!(best(&store, maze(1)), Some(450));
+        assert_eq!(best(&store, maze(2)), Some(700));
+        assert_eq!(best(&store, MazeId { level: 3, ..maze(1) }), None);
+        assert_eq!(best(&store, MazeId { algorithm: Algorithm::Kruskal, ..maze(1) }), None);
+        assert_eq!(best(&store, MazeId { start: Point { x: 0, y: 0 }, ..maze(1) }), None);
+    }
+
+    #[test]
+    fn the_oldest_record_makes_room() {
+        let mut store = Store::mount(MockFlash::new(1024, 2));
+        for seed in 0 .. RECORDS as u64 {
+            keep(&mut store, maze(seed), 100 + seed as u32).unwrap();
+        }
+        // Beating it again keeps the first one around
+        keep(&mut store, maze(0), 90).unwrap();
+        keep(&mut store, maze(100), 300).unwrap();
+        assert_eq!(best(&store, maze(0)), Some(90));
+        assert_eq!(best(&store, maze(1)), None);
+        for seed in 2 .. RECORDS as u64 {
+            assert_eq!(best(&store, maze(seed)), Some(100 + seed as u32));
+        }
+        assert_eq!(best(&store, maze(100)), Some(300));
+    }
+}
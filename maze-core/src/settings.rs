@@ -0,0 +1,154 @@
+//! What the game remembers across resets, kept in the flash `Store`.
+
+use crate::brightness::MAX_BRIGHTNESS;
+use crate::game::Mode;
+use crate::store::{Flash, Store, StoreError};
+
+/// Keys of the settings in the store. Never reuse a number for something else.
+mod key {
+    pub const LEVEL: u8 = 1;
+    pub const MUTED: u8 = 2;
+    pub const JOYSTICK_CENTRE: u8 = 3;
+    pub const SEED: u8 = 4;
+    pub const BRIGHTNESS: u8 = 5;
+    pub const AUTO_BRIGHTNESS: u8 = 6;
+    pub const AUTO_FINISH: u8 = 7;
+    pub const MODE: u8 = 8;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub level: u8,
+    pub muted: bool,
+    /// ADC readings of the joystick at rest, `None` until it has been calibrated.
+    pub joystick_centre: Option<(u16, u16)>,
+    /// Seed of the maze to start on after a reset, as last chosen with the `seed` command.
+    /// Not the maze being played, writing every new one would wear the flash.
+    pub seed: Option<u64>,
+    /// Brightness of the panel, from 1 to `MAX_BRIGHTNESS`.
+    pub brightness: u8,
+    /// Whether the panel dims with the ambient light.
+    pub auto_brightness: bool,
+    /// Whether the level is finished as soon as the ball gets to the exit, instead of with a press there.
+    pub auto_finish: bool,
+    /// How the time counts, see `game::Mode`.
+    pub mode: Mode,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            level: 0,
+            muted: false,
+            joystick_centre: None,
+            seed: None,
+            brightness: MAX_BRIGHTNESS,
+            auto_brightness: false,
+            auto_finish: true,
+            mode: Mode::Free,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings, using the defaults for any that are missing or unreadable.
+    pub fn load<F: Flash>(store: &Store<F>) -> Settings {
+        let mut settings = Settings::default();
+        let mut buffer = [0; 8];
+        if store.get(key::LEVEL, &mut buffer) == Some(1) {
+            settings.level = buffer[0];
+        }
+        if store.get(key::MUTED, &mut buffer) == Some(1) {
+            settings.muted = buffer[0] != 0;
+        }
+        if store.get(key::JOYSTICK_CENTRE, &mut buffer) == Some(4) {
+            settings.joystick_centre = Some((
+                u16::from_le_bytes([buffer[0], buffer[1]]),
+                u16::from_le_bytes([buffer[2], buffer[3]]),
+            ));
+        }
+        if store.get(key::SEED, &mut buffer) == Some(8) {
+            settings.seed = Some(u64::from_le_bytes(buffer));
+        }
+        if store.get(key::BRIGHTNESS, &mut buffer) == Some(1) && (1 ..= MAX_BRIGHTNESS).contains(&buffer[0]) {
+            settings.brightness = buffer[0];
+        }
+        if store.get(key::AUTO_BRIGHTNESS, &mut buffer) == Some(1) {
+            settings.auto_brightness = buffer[0] != 0;
+        }
+        if store.get(key::AUTO_FINISH, &mut buffer) == Some(1) {
+            settings.auto_finish = buffer[0] != 0;
+        }
+        if store.get(key::MODE, &mut buffer) == Some(1) {
+            settings.mode = Mode::from_u8(buffer[0]).unwrap_or(settings.mode);
+        }
+        settings
+    }
+
+    /// Writes the settings. Only the ones that changed cost any flash.
+    pub fn save<F: Flash>(&self, store: &mut Store<F>) -> Result<(), StoreError<F::Error>> {
+        store.set(key::LEVEL, &[self.level])?;
+        store.set(key::MUTED, &[self.muted as u8])?;
+        if let Some((x, y)) = self.joystick_centre {
+            let x = x.to_le_bytes();
+            let y = y.to_le_bytes();
+            store.set(key::JOYSTICK_CENTRE, &[x[0], x[1], y[0], y[1]])?;
+        }
+        if let Some(seed) = self.seed {
+            store.set(key::SEED, &seed.to_le_bytes())?;
+        }
+        store.set(key::BRIGHTNESS, &[self.brightness])?;
+        store.set(key::AUTO_BRIGHTNESS, &[self.auto_brightness as u8])?;
+        store.set(key::AUTO_FINISH, &[self.auto_finish as u8])?;
+        store.set(key::MODE, &[self.mode as u8])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::MockFlash;
+
+    #[test]
+    fn fresh_flash_gives_the_defaults() {
+        let store = Store::mount(MockFlash::new(1024, 2));
+        assert_eq!(Settings::load(&store), Settings::default());
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut store = Store::mount(MockFlash::new(1024, 2));
+        let settings = Settings {
+            level: 4,
+            muted: true,
+            joystick_centre: Some((2047, 1990)),
+            seed: Some(0x0123_4567_89ab_cdef),
+            brightness: 3,
+            auto_brightness: true,
+            auto_finish: false,
+            mode: Mode::Countdown,
+        };
+        settings.save(&mut store).unwrap();
+        let store = Store::mount(store.release());
+        assert_eq!(Settings::load(&store), settings);
+    }
+
+    #[test]
+    fn saving_unchanged_settings_writes_nothing() {
+        let mut store = Store::mount(MockFlash::new(1024, 2));
+        let mut settings = Settings { level: 2, ..Settings::default() };
+        settings.save(&mut store).unwrap();
+        let before = store.release();
+        let data = before.data.clone();
+        let mut store = Store::mount(before);
+        settings.save(&mut store).unwrap();
+        assert!(store.release().data == data);
+
+        let mut store = Store::mount(MockFlash::new(1024, 2));
+        settings.save(&mut store).unwrap();
+        settings.level = 3;
+        settings.save(&mut store).unwrap();
+        assert_eq!(Settings::load(&store).level, 3);
+    }
+}
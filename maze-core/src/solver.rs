@@ -0,0 +1,310 @@
+use crate::maze::{BitMap, Direction, DirectionMap, Maze, Point, MAX_HEIGHT, MAX_WIDTH};
+use core::fmt;
+
+/// Longest possible path: one step into every cell but the first one.
+const MAX_STEPS: usize = MAX_WIDTH as usize * MAX_HEIGHT as usize - 1;
+/// Two bits per step. The cell count is a multiple of four, so this rounds up.
+const STEP_BYTES: usize = (MAX_STEPS + 1) / 4;
+
+/// A route through the maze, stored as the directions taken from `start`.
+///
+/// Directions take two bits each, so even a path through every cell of the
+/// maze is only 128 bytes.
+pub struct Path {
+	pub start: Point,
+	steps: [u8; STEP_BYTES],
+	len: u16,
+}
+impl Path {
+	fn new(start: Point) -> Path {
+		Path { start, steps: [0; STEP_BYTES], len: 0 }
+	}
+	/// Number of moves from `start` to the destination.
+	pub fn len(&self) -> u16 {
+		self.len
+	}
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+	pub fn get(&self, index: u16) -> Option<Direction> {
+		if index >= self.len {
+			return None;
+		}
+		let byte = self.steps[(index >> 2) as usize];
+		Some(Direction::from_bits(byte >> ((index & 0b11) << 1)))
+	}
+	fn set(&mut self, index: u16, dir: Direction) {
+		let shift = (index & 0b11) << 1;
+		let byte = &mut self.steps[(index >> 2) as usize];
+		*byte = (*byte & !(0b11 << shift)) | ((dir as u8) << shift);
+	}
+	pub fn directions(&self) -> PathDirections<'_> {
+		PathDirections { path: self, index: 0 }
+	}
+	/// Every cell on the path, starting with `start` and ending with the destination.
+	pub fn points(&self) -> PathPoints<'_> {
+		PathPoints { directions: self.directions(), next: Some(self.start) }
+	}
+}
+
+/// One letter per step, `U`, `D`, `L` or `R`, so `RRD` is two steps right and one down.
+impl fmt::Display for Path {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		use core::fmt::Write;
+		for dir in self.directions() {
+			f.write_char(match dir {
+				Direction::Top => 'U',
+				Direction::Bottom => 'D',
+				Direction::Left => 'L',
+				Direction::Right => 'R',
+			})?;
+		}
+		Ok(())
+	}
+}
+
+pub struct PathDirections<'a> {
+	path: &'a Path,
+	index: u16,
+}
+impl<'a> Iterator for PathDirections<'a> {
+	type Item = Direction;
+	fn next(&mut self) -> Option<Self::Item> {
+		let dir = self.path.get(self.index)?;
+		self.index += 1;
+		Some(dir)
+	}
+}
+
+pub struct PathPoints<'a> {
+	directions: PathDirections<'a>,
+	next: Option<Point>,
+}
+impl<'a> Iterator for PathPoints<'a> {
+	type Item = Point;
+	fn next(&mut self) -> Option<Self::Item> {
+		let current = self.next?;
+		self.next = self.directions.next().map(|dir| current.dir(dir));
+		Some(current)
+	}
+}
+
+/// Breadth first search done one distance at a time: every cell in `frontier`
+/// is exactly `distance` moves away from the origin. Instead of a queue this
+/// only needs a few bitmaps, which keeps it usable within our 8K of RAM.
+struct Flood {
+	visited: BitMap,
+	frontier: BitMap,
+	distance: u16,
+}
+impl Flood {
+	fn new(origin: Point) -> Flood {
+		let mut flood = Flood {
+			visited: BitMap::new(false),
+			frontier: BitMap::new(false),
+			distance: 0,
+		};
+		flood.visited.set(origin, true);
+		flood.frontier.set(origin, true);
+		flood
+	}
+
+	/// Moves the frontier one step further, calling `reached` with every new cell
+	/// and the direction it was entered in. Returns false when nothing was left to reach.
+	fn advance<F>(&mut self, maze: &Maze, mut reached: F) -> bool
+	where F: FnMut(Point, Direction) {
+		let mut next = BitMap::new(false);
+		let mut advanced = false;
+		for current in maze.dimensions.points() {
+			if !self.frontier.get(current) {
+				continue;
+			}
+			for &dir in &Direction::ALL {
+				if !maze.is_open(current, dir) {
+					continue;
+				}
+				let neighbour = current.dir(dir);
+				if self.visited.get(neighbour) {
+					continue;
+				}
+				self.visited.set(neighbour, true);
+				next.set(neighbour, true);
+				reached(neighbour, dir);
+				advanced = true;
+			}
+		}
+		if advanced {
+			self.frontier = next;
+			self.distance += 1;
+		}
+		advanced
+	}
+}
+
+impl Maze {
+	/// One of the cells furthest away from `from`, along with its distance.
+	pub fn farthest_from(&self, from: Point) -> (Point, u16) {
+		let mut flood = Flood::new(from);
+		let mut farthest = from;
+		while flood.advance(self, |neighbour, _| farthest = neighbour) {}
+		(farthest, flood.distance)
+	}
+
+	/// Number of cells that can be reached from `from`, itself included.
+	pub fn reachable_from(&self, from: Point) -> u16 {
+		let mut flood = Flood::new(from);
+		let mut reached = 1;
+		while flood.advance(self, |_, _| reached += 1) {}
+		reached
+	}
+
+	/// Shortest path from `start` to `end`, or `None` if `end` can't be reached.
+	pub fn solve(&self) -> Option<Path> {
+		self.path(self.start, self.end)
+	}
+
+	/// Shortest path between two cells, or `None` if they are not connected.
+	///
+	/// On top of the three bitmaps of the flood fill this keeps the direction each
+	/// cell was reached from, so the memory use is fixed at a bit over 300 bytes
+	/// regardless of the maze. It doesn't assume the maze is perfect, so loops are fine.
+	pub fn path(&self, from: Point, to: Point) -> Option<Path> {
+		let mut flood = Flood::new(from);
+		let mut parents = DirectionMap::new();
+		while !flood.frontier.get(to) {
+			// Remember the way back for every cell reached
+			let advanced = flood.advance(self, |neighbour, dir| parents.set(neighbour, dir.opposite()));
+			if !advanced {
+				return None;
+			}
+		}
+		let distance = flood.distance;
+
+		// Walk back from the destination, filling in the steps last to first
+		let mut path = Path::new(from);
+		path.len = distance;
+		let mut current = to;
+		for index in (0..distance).rev() {
+			let back = parents.get(current);
+			path.set(index, back.opposite());
+			current = current.dir(back);
+		}
+		Some(path)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algorithms::MazeAlgorithm;
+	use crate::maze::MazeGenerator;
+
+	fn generated() -> Maze {
+		let mut maze = Maze::new();
+		MazeGenerator::new().generate(&mut maze, || {});
+		maze
+	}
+
+	#[test]
+	fn path_prints_as_letters() {
+		let mut path = Path::new(Point { x: 0, y: 0 });
+		for (index, &dir) in [Direction::Right, Direction::Right, Direction::Bottom, Direction::Left, Direction::Top].iter().enumerate() {
+			path.set(index as u16, dir);
+		}
+		path.len = 5;
+		assert_eq!(path.to_string(), "RRDLU");
+		assert_eq!(Path::new(Point { x: 0, y: 0 }).to_string(), "");
+	}
+
+	#[test]
+	fn solution_follows_open_walls_to_the_end() {
+		let maze = generated();
+		let path = maze.solve().unwrap();
+		let mut current = maze.start;
+		for dir in path.directions() {
+			assert!(maze.is_open(current, dir));
+			current = current.dir(dir);
+		}
+		assert!(current == maze.end);
+		assert_eq!(path.points().count(), path.len() as usize + 1);
+		assert!(path.points().last().unwrap() == maze.end);
+	}
+
+	#[test]
+	fn path_to_itself_is_empty() {
+		let maze = generated();
+		let path = maze.path(maze.end, maze.end).unwrap();
+		assert!(path.is_empty());
+		assert!(path.get(0).is_none());
+		assert!(path.points().eq(core::iter::once(maze.end)));
+	}
+
+	#[test]
+	fn walled_off_cells_have_no_path() {
+		let maze = Maze::new();
+		assert!(maze.path(Point { x: 0, y: 0 }, Point { x: 1, y: 0 }).is_none());
+	}
+
+	#[test]
+	fn takes_the_shortcut_through_a_loop() {
+		let mut maze = Maze::new();
+		// A ring around the cells (0, 0) - (2, 1) ...
+		for x in 0..2 {
+			maze.break_wall(Point { x, y: 0 }, Direction::Right);
+			maze.break_wall(Point { x, y: 1 }, Direction::Right);
+		}
+		maze.break_wall(Point { x: 0, y: 0 }, Direction::Bottom);
+		maze.break_wall(Point { x: 2, y: 0 }, Direction::Bottom);
+		let from = Point { x: 0, y: 1 };
+		let to = Point { x: 2, y: 1 };
+		assert_eq!(maze.path(from, to).unwrap().len(), 2);
+		// ... going the long way round once the bottom is blocked
+		maze.bitmap_left.set(Point { x: 1, y: 1 }, true);
+		let path = maze.path(from, to).unwrap();
+		assert_eq!(path.len(), 4);
+		assert!(path.directions().eq([Direction::Top, Direction::Right, Direction::Right, Direction::Bottom].iter().cloned()));
+	}
+
+	#[test]
+	fn farthest_cell_is_at_the_end_of_the_longest_path() {
+		let maze = generated();
+		let (farthest, distance) = maze.farthest_from(maze.start);
+		assert_eq!(maze.path(maze.start, farthest).unwrap().len(), distance);
+		for p in maze.dimensions.points() {
+			assert!(maze.path(maze.start, p).unwrap().len() <= distance);
+		}
+	}
+
+	#[test]
+	fn small_mazes_have_their_own_border() {
+		let mut maze = Maze::with_dimensions(crate::maze::Dimensions::SMALL);
+		for x in 0..7 {
+			maze.break_wall(Point { x, y: 0 }, Direction::Right);
+		}
+		// Breaking the wall past the edge of the maze doesn't open a way out
+		maze.break_wall(Point { x: 7, y: 0 }, Direction::Right);
+		let path = maze.path(Point { x: 0, y: 0 }, Point { x: 7, y: 0 }).unwrap();
+		assert_eq!(path.len(), 7);
+		assert!(maze.path(Point { x: 0, y: 0 }, Point { x: 8, y: 0 }).is_none());
+		assert_eq!(maze.reachable_from(Point { x: 0, y: 0 }), 8);
+	}
+
+	#[test]
+	fn path_through_every_cell() {
+		let mut maze = Maze::new();
+		// A single snake going right on even rows and left on odd ones
+		for y in 0..MAX_HEIGHT {
+			for x in 0..MAX_WIDTH - 1 {
+				maze.break_wall(Point { x, y }, Direction::Right);
+			}
+			if y < MAX_HEIGHT - 1 {
+				let x = if y & 1 == 0 { MAX_WIDTH - 1 } else { 0 };
+				maze.break_wall(Point { x, y }, Direction::Bottom);
+			}
+		}
+		let to = Point { x: if MAX_HEIGHT & 1 == 0 { 0 } else { MAX_WIDTH - 1 }, y: MAX_HEIGHT - 1 };
+		let path = maze.path(Point { x: 0, y: 0 }, to).unwrap();
+		assert_eq!(path.len() as usize, MAX_STEPS);
+		assert!(path.points().last().unwrap() == to);
+	}
+}
@@ -0,0 +1,450 @@
+//! Small key/value store for flash memory.
+//!
+//! Values are appended to the active page as records, the last valid record of
+//! a key being its current value. Once the page is full, the latest record of
+//! every key is copied to the next page and the store moves there, so the
+//! erases go round all the pages instead of wearing out a single one.
+//!
+//! Page layout: a 4 byte header, `KV` and a little endian sequence number that
+//! tells the newest page apart, then the records:
+//!
+//! | size         | content                                      |
+//! |--------------|----------------------------------------------|
+//! | 1            | key, `0xff` marks the erased end of the page |
+//! | 1            | value length                                 |
+//! | length       | value, padded with `0xff` to an even length  |
+//! | 2            | CRC-16 of key, length and value              |
+//!
+//! Everything is written in halfwords, as the STM32F0 flash requires. The header
+//! of a page is only written once everything has been copied into it, and a
+//! record torn by a reset fails its CRC, so losing power at any point only ever
+//! loses the value being written.
+
+use crate::crc::crc16;
+
+/// Flash memory set aside for the store, addressed from its start.
+pub trait Flash {
+    type Error;
+
+    /// Size of an erasable page in bytes.
+    fn page_size(&self) -> u32;
+    /// Number of pages of the store, at least two.
+    fn pages(&self) -> u16;
+    fn read(&self, offset: u32, buffer: &mut [u8]);
+    /// Programs `data` at `offset`, both aligned on halfwords. The bytes have to be erased.
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+    /// Sets every byte of `page` to `0xff`.
+    fn erase(&mut self, page: u16) -> Result<(), Self::Error>;
+}
+
+/// Longest value that can be stored.
+pub const MAX_VALUE_LEN: usize = 64;
+const MAGIC: [u8; 2] = *b"KV";
+const PAGE_HEADER_LEN: u32 = 4;
+const ERASED: u8 = 0xff;
+const MAX_RECORD_LEN: usize = 2 + MAX_VALUE_LEN + 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StoreError<E> {
+    Flash(E),
+    /// `0xff` can't be used as a key.
+    InvalidKey,
+    ValueTooLong,
+    /// The latest values don't fit in a page any more.
+    Full,
+}
+
+fn record_len(value_len: usize) -> u32 {
+    (2 + value_len + (value_len & 1) + 2) as u32
+}
+
+/// Whether sequence number `a` comes after `b`, allowing for wrapping around.
+fn newer(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) > 0
+}
+
+/// A record found in a page.
+#[derive(Copy, Clone)]
+struct Record {
+    key: u8,
+    len: u8,
+    /// Offset of the record within its page.
+    offset: u32,
+    valid: bool,
+}
+
+pub struct Store<F: Flash> {
+    flash: F,
+    /// The page with the newest sequence number, `None` until the first write.
+    page: Option<u16>,
+    sequence: u16,
+    /// Offset of the first erased byte in the active page.
+    end: u32,
+}
+
+impl<F: Flash> Store<F> {
+    /// Finds the active page, an empty store if there is none.
+    pub fn mount(flash: F) -> Store<F> {
+        let mut store = Store { flash, page: None, sequence: 0, end: PAGE_HEADER_LEN };
+        for page in 0 .. store.flash.pages() {
+            let mut header = [0; PAGE_HEADER_LEN as usize];
+            store.flash.read(store.page_offset(page), &mut header);
+            if header[0 .. 2] != MAGIC {
+                continue;
+            }
+            let sequence = u16::from_le_bytes([header[2], header[3]]);
+            if store.page.is_none() || newer(sequence, store.sequence) {
+                store.page = Some(page);
+                store.sequence = sequence;
+            }
+        }
+        if let Some(page) = store.page {
+            store.end = store.scan_end(page);
+        }
+        store
+    }
+
+    /// Copies the value of `key` into `buffer` and returns its length.
+    pub fn get(&self, key: u8, buffer: &mut [u8]) -> Option<usize> {
+        let page = self.page?;
+        let record = self.latest(page, key)?;
+        let len = core::cmp::min(record.len as usize, buffer.len());
+        self.flash.read(self.page_offset(page) + record.offset + 2, &mut buffer[.. len]);
+        Some(record.len as usize)
+    }
+
+    /// Stores `value` for `key`. Nothing is written if that already is its value.
+    pub fn set(&mut self, key: u8, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        if key == ERASED {
+            return Err(StoreError::InvalidKey);
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(StoreError::ValueTooLong);
+        }
+        let mut current = [0; MAX_VALUE_LEN];
+        if let Some(len) = self.get(key, &mut current) {
+            if current[.. len] == *value {
+                return Ok(());
+            }
+        }
+
+        let len = record_len(value.len());
+        match self.page {
+            Some(page) if self.end + len <= self.flash.page_size() => {
+                let result = self.write_record(page, self.end, key, value);
+                // Even a torn record takes its room, that flash can't be written again
+                self.end += len;
+                result
+            }
+            _ => self.compact(key, value),
+        }
+    }
+
+    /// Gives the flash memory back.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    fn page_offset(&self, page: u16) -> u32 {
+        page as u32 * self.flash.page_size()
+    }
+
+    fn record_at(&self, page: u16, offset: u32) -> Option<Record> {
+        if offset + record_len(0) > self.flash.page_size() {
+            return None;
+        }
+        let mut buffer = [0; MAX_RECORD_LEN];
+        let base = self.page_offset(page) + offset;
+        self.flash.read(base, &mut buffer[.. 2]);
+        let (key, len) = (buffer[0], buffer[1]);
+        if key == ERASED {
+            return None;
+        }
+        let size = record_len(len as usize);
+        if offset + size > self.flash.page_size() {
+            return None;
+        }
+        let mut valid = false;
+        if len as usize <= MAX_VALUE_LEN {
+            let size = size as usize;
+            self.flash.read(base, &mut buffer[.. size]);
+            let checksum = u16::from_le_bytes([buffer[size - 2], buffer[size - 1]]);
+            valid = crc16(&buffer[.. 2 + len as usize]) == checksum;
+        }
+        Some(Record { key, len, offset, valid })
+    }
+
+    fn records(&self, page: u16) -> Records<'_, F> {
+        Records { store: self, page, offset: PAGE_HEADER_LEN }
+    }
+
+    fn scan_end(&self, page: u16) -> u32 {
+        self.records(page)
+            .last()
+            .map(|record| record.offset + record_len(record.len as usize))
+            .unwrap_or(PAGE_HEADER_LEN)
+    }
+
+    fn latest(&self, page: u16, key: u8) -> Option<Record> {
+        self.records(page).filter(|record| record.valid && record.key == key).last()
+    }
+
+    fn write_record(&mut self, page: u16, offset: u32, key: u8, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        let mut buffer = [ERASED; MAX_RECORD_LEN];
+        buffer[0] = key;
+        buffer[1] = value.len() as u8;
+        buffer[2 .. 2 + value.len()].copy_from_slice(value);
+        let checksum = crc16(&buffer[.. 2 + value.len()]);
+        let len = record_len(value.len()) as usize;
+        buffer[len - 2 .. len].copy_from_slice(&checksum.to_le_bytes());
+        let base = self.page_offset(page);
+        self.flash.write(base + offset, &buffer[.. len]).map_err(StoreError::Flash)
+    }
+
+    /// Whether `record` of `page` holds the latest value of a key other than `key`.
+    fn kept(&self, page: u16, record: &Record, key: u8) -> bool {
+        record.valid && record.key != key && self.latest(page, record.key).map(|latest| latest.offset) == Some(record.offset)
+    }
+
+    /// Moves the latest values and the new one to the next page.
+    fn compact(&mut self, key: u8, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        let len = record_len(value.len());
+        // Nothing gets erased unless it all fits, or every later write would erase again
+        let kept = self.page.map_or(0, |page| {
+            self.records(page)
+                .filter(|record| self.kept(page, record, key))
+                .map(|record| record_len(record.len as usize))
+                .sum()
+        });
+        if PAGE_HEADER_LEN + kept + len > self.flash.page_size() {
+            return Err(StoreError::Full);
+        }
+
+        let target = match self.page {
+            Some(page) => (page + 1) % self.flash.pages(),
+            None => 0,
+        };
+        self.flash.erase(target).map_err(StoreError::Flash)?;
+        let mut offset = PAGE_HEADER_LEN;
+        if let Some(page) = self.page {
+            let mut buffer = [0; MAX_RECORD_LEN];
+            let mut next = PAGE_HEADER_LEN;
+            while let Some(record) = self.record_at(page, next) {
+                next += record_len(record.len as usize);
+                if !self.kept(page, &record, key) {
+                    continue;
+                }
+                let len = record_len(record.len as usize);
+                self.flash.read(self.page_offset(page) + record.offset, &mut buffer[.. len as usize]);
+                let base = self.page_offset(target);
+                self.flash.write(base + offset, &buffer[.. len as usize]).map_err(StoreError::Flash)?;
+                offset += len;
+            }
+        }
+        self.write_record(target, offset, key, value)?;
+
+        // The page only counts once its header is there
+        let sequence = if self.page.is_some() { self.sequence.wrapping_add(1) } else { 0 };
+        let mut header = [0; PAGE_HEADER_LEN as usize];
+        header[0 .. 2].copy_from_slice(&MAGIC);
+        header[2 .. 4].copy_from_slice(&sequence.to_le_bytes());
+        let base = self.page_offset(target);
+        self.flash.write(base, &header).map_err(StoreError::Flash)?;
+        self.page = Some(target);
+        self.sequence = sequence;
+        self.end = offset + len;
+        Ok(())
+    }
+}
+
+struct Records<'a, F: Flash> {
+    store: &'a Store<F>,
+    page: u16,
+    offset: u32,
+}
+
+impl<'a, F: Flash> Iterator for Records<'a, F> {
+    type Item = Record;
+    fn next(&mut self) -> Option<Record> {
+        let record = self.store.record_at(self.page, self.offset)?;
+        self.offset += record_len(record.len as usize);
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Flash in RAM that checks the rules of the real one.
+    pub(crate) struct MockFlash {
+        pub data: Vec<u8>,
+        pub page_size: u32,
+        pub erases: Vec<u32>,
+        /// Halfwords left to program before the power goes out.
+        pub power: Option<usize>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct PowerLoss;
+
+    impl MockFlash {
+        pub fn new(page_size: u32, pages: u16) -> MockFlash {
+            MockFlash {
+                data: vec![ERASED; (page_size * pages as u32) as usize],
+                page_size,
+                erases: vec![0; pages as usize],
+                power: None,
+            }
+        }
+    }
+
+    impl Flash for MockFlash {
+        type Error = PowerLoss;
+        fn page_size(&self) -> u32 {
+            self.page_size
+        }
+        fn pages(&self) -> u16 {
+            self.erases.len() as u16
+        }
+        fn read(&self, offset: u32, buffer: &mut [u8]) {
+            let offset = offset as usize;
+            buffer.copy_from_slice(&self.data[offset .. offset + buffer.len()]);
+        }
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), PowerLoss> {
+            assert!(offset & 1 == 0 && data.len() & 1 == 0, "unaligned write");
+            for (index, halfword) in data.chunks(2).enumerate() {
+                if let Some(power) = &mut self.power {
+                    if *power == 0 {
+                        return Err(PowerLoss);
+                    }
+                    *power -= 1;
+                }
+                let at = offset as usize + index * 2;
+                assert!(self.data[at .. at + 2] == [ERASED, ERASED], "programming a written halfword");
+                self.data[at .. at + 2].copy_from_slice(halfword);
+            }
+            Ok(())
+        }
+        fn erase(&mut self, page: u16) -> Result<(), PowerLoss> {
+            if self.power == Some(0) {
+                return Err(PowerLoss);
+            }
+            let start = (page as u32 * self.page_size) as usize;
+            for byte in &mut self.data[start .. start + self.page_size as usize] {
+                *byte = ERASED;
+            }
+            self.erases[page as usize] += 1;
+            Ok(())
+        }
+    }
+
+    fn get(store: &Store<MockFlash>, key: u8) -> Option<Vec<u8>> {
+        let mut buffer = [0; MAX_VALUE_LEN];
+        store.get(key, &mut buffer).map(|len| buffer[.. len].to_vec())
+    }
+
+    #[test]
+    fn empty_flash_has_no_values() {
+        let store = Store::mount(MockFlash::new(128, 2));
+        assert_eq!(get(&store, 1), None);
+    }
+
+    #[test]
+    fn values_survive_a_remount() {
+        let mut store = Store::mount(MockFlash::new(128, 2));
+        store.set(1, b"abc").unwrap();
+        store.set(2, &[7]).unwrap();
+        store.set(1, b"de").unwrap();
+        store.set(3, &[]).unwrap();
+        let store = Store::mount(store.release());
+        assert_eq!(get(&store, 1), Some(b"de".to_vec()));
+        assert_eq!(get(&store, 2), Some(vec![7]));
+        assert_eq!(get(&store, 3), Some(vec![]));
+        assert_eq!(get(&store, 4), None);
+    }
+
+    #[test]
+    fn bad_keys_and_values_are_refused() {
+        let mut store = Store::mount(MockFlash::new(128, 2));
+        assert_eq!(store.set(ERASED, &[1]), Err(StoreError::InvalidKey));
+        assert_eq!(store.set(1, &[0; MAX_VALUE_LEN + 1]), Err(StoreError::ValueTooLong));
+    }
+
+    #[test]
+    fn unchanged_values_are_not_written_again() {
+        let mut store = Store::mount(MockFlash::new(128, 2));
+        store.set(1, &[5, 5]).unwrap();
+        let end = store.end;
+        store.set(1, &[5, 5]).unwrap();
+        assert_eq!(store.end, end);
+    }
+
+    #[test]
+    fn full_pages_move_on_and_spread_the_erases() {
+        let mut store = Store::mount(MockFlash::new(64, 4));
+        for round in 0 .. 200_u32 {
+            store.set(1, &round.to_le_bytes()).unwrap();
+            store.set(2, &[round as u8]).unwrap();
+        }
+        store.set(3, b"kept").unwrap();
+        let flash = store.release();
+        let erases = flash.erases.clone();
+        assert!(erases.iter().max().unwrap() - erases.iter().min().unwrap() <= 1, "{:?}", erases);
+        let store = Store::mount(flash);
+        assert_eq!(get(&store, 1), Some(199_u32.to_le_bytes().to_vec()));
+        assert_eq!(get(&store, 2), Some(vec![199]));
+        assert_eq!(get(&store, 3), Some(b"kept".to_vec()));
+    }
+
+    #[test]
+    fn too_many_values_for_a_page_are_refused() {
+        let mut store = Store::mount(MockFlash::new(32, 2));
+        // Each takes 8 bytes, there's room for three after the header
+        store.set(1, &[1; 4]).unwrap();
+        store.set(2, &[2; 4]).unwrap();
+        store.set(3, &[3; 4]).unwrap();
+        let erases = store.flash.erases.clone();
+        assert_eq!(store.set(4, &[4; 4]), Err(StoreError::Full));
+        assert_eq!(store.set(4, &[4; 4]), Err(StoreError::Full));
+        assert_eq!(store.flash.erases, erases, "erased a page without room for the values");
+        assert_eq!(get(&store, 3), Some(vec![3; 4]));
+        // Replacing a value still works, it doesn't need more room
+        store.set(2, &[9; 4]).unwrap();
+        assert_eq!(get(&Store::mount(store.release()), 2), Some(vec![9; 4]));
+    }
+
+    #[test]
+    fn power_loss_only_loses_the_value_being_written() {
+        // Cut the power at every possible point of a write, appending or compacting
+        for cut in 0 .. 40 {
+            let mut store = Store::mount(MockFlash::new(64, 2));
+            for round in 0 .. 5_u8 {
+                store.set(1, &[round; 6]).unwrap();
+            }
+            store.set(2, b"ok").unwrap();
+            let mut flash = store.release();
+            flash.power = Some(cut);
+            let mut store = Store::mount(flash);
+            let result = store.set(1, &[9; 6]);
+
+            let mut flash = store.release();
+            flash.power = None;
+            let store = Store::mount(flash);
+            let value = get(&store, 1).unwrap();
+            if result.is_ok() {
+                assert_eq!(value, vec![9; 6]);
+            } else {
+                assert!(value == vec![9; 6] || value == vec![4; 6], "cut {}: {:?}", cut, value);
+            }
+            assert_eq!(get(&store, 2), Some(b"ok".to_vec()), "cut {}", cut);
+        }
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        assert!(newer(1, 0));
+        assert!(newer(0, u16::MAX));
+        assert!(!newer(u16::MAX, 0));
+    }
+}
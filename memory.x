@@ -2,10 +2,15 @@ MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   /* These values correspond to the LM3S6965, one of the few devices QEMU can emulate */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 60K
+  /* The last four 1K pages of the 64K are kept for the settings, see src/flash.rs */
+  STORAGE : ORIGIN = 0x0800F000, LENGTH = 4K
   RAM : ORIGIN = 0x20000000, LENGTH = 8K
 }
 
+_storage_start = ORIGIN(STORAGE);
+_storage_end = ORIGIN(STORAGE) + LENGTH(STORAGE);
+
 /* This is where the call stack will be allocated. */
 /* The stack is of the full descending type. */
 /* You may want to use this variable to locate the call stack and static
@@ -0,0 +1,367 @@
+//! Plays the maze in a terminal, through the same `dma::DmaScan` as the firmware.
+//!
+//! The game itself is the `game::Game` of the firmware: the keys are turned into
+//! its events and the simulator does the effects it asks for, the way the firmware
+//! does. The arrow keys (or WASD) tilt the joystick, space or enter presses its
+//! button and `m` holds it down long enough to open or close the menu. `p` pauses,
+//! `n` generates a new maze at the current level and `q` quits. A seed can be given
+//! as an argument, in decimal or `0x` hex, to replay a maze from the console, or
+//! a whole maze as printed by its `export` command, to play that one first.
+
+mod screen;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, terminal};
+use maze_core::algorithms::{next_seed, Algorithm, Generator};
+use maze_core::ball::Ball;
+use maze_core::brightness::MAX_BRIGHTNESS;
+use maze_core::console::Command;
+use maze_core::decoder::{self, Decoder};
+use maze_core::difficulty::Difficulty;
+use maze_core::display;
+use maze_core::dma::{DataLines, DmaScan, PortPalette};
+use maze_core::framebuffer::{Framebuffer, ROWS};
+use maze_core::game::{self, Effect, Generate, Mode, State};
+use maze_core::hud::{self, Status};
+use maze_core::maze::Maze;
+use maze_core::menu::{self, Action, Stick};
+use maze_core::palette::COLOUR_BITS;
+use maze_core::records::MazeId;
+use std::cell::RefCell;
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, process};
+
+/// Same wiring of the data lines as `panel_dma::LINES` in the firmware.
+const LINES: DataLines = DataLines { r1: 0, g1: 1, b1: 2, r2: 3, g2: 4, b2: 5 };
+static PORT_PALETTE: PortPalette = PortPalette::new(&display::PALETTE, &LINES);
+
+/// Period of the input task, TIM14 runs at 100 Hz on the board.
+const INPUT_PERIOD: Duration = Duration::from_millis(1000 / game::TICKS_PER_SECOND as u64);
+/// The terminal can't keep up with the refresh rate of the panel, so it only gets about 30 frames a second.
+const FRAME_PERIOD: Duration = Duration::from_millis(33);
+/// Move of the ball per input period with a key held, about the joystick pushed half way.
+const TILT: i16 = 8;
+/// Terminals only send repeats of a held key, so it counts as released once they stop.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+/// What the firmware keeps in its resources.
+struct Board {
+    game: game::Game,
+    stick: Stick,
+    maze: Maze,
+    ball: Ball,
+    algorithm: Algorithm,
+    framebuffer: Framebuffer,
+    brightness: u8,
+    muted: bool,
+    /// Best times, which the firmware keeps in flash with `records`.
+    records: Vec<(MazeId, u32)>,
+    /// Maze given on the command line, played instead of generating the first one.
+    imported: Option<Maze>,
+}
+
+impl Board {
+    fn new(seed: u64, mode: Mode, imported: Option<Maze>) -> Board {
+        let mut board = Board {
+            game: game::Game::new(0),
+            stick: Stick::new(),
+            maze: Maze::new(),
+            ball: Ball::new(),
+            algorithm: Algorithm::Backtracker,
+            framebuffer: Framebuffer::new(),
+            brightness: MAX_BRIGHTNESS,
+            muted: false,
+            records: Vec::new(),
+            imported,
+        };
+        board.game.set_mode(mode);
+        board.handle(game::Event::Booted { seed });
+        board
+    }
+
+    /// Hands `event` to the game and does what it asks for.
+    fn handle(&mut self, event: game::Event) {
+        if let Some(effect) = self.game.handle(event) {
+            self.effect(effect);
+        }
+    }
+
+    /// Same as the effect task of the firmware, without a buzzer to play anything on.
+    fn effect(&mut self, effect: Effect) {
+        match effect {
+            Effect::Generate(request) => self.generate(&request),
+            Effect::Celebrate { record: Some(ticks) } => {
+                let id = self.maze_id();
+                self.records.retain(|&(maze, _)| maze != id);
+                self.records.push((id, ticks));
+            }
+            Effect::Celebrate { record: None } | Effect::Beep => {}
+            Effect::Menu(Action::Algorithm(algorithm)) => self.algorithm = algorithm,
+            Effect::Menu(Action::Brighter) => self.brightness = (self.brightness + 1).min(MAX_BRIGHTNESS),
+            Effect::Menu(Action::Dimmer) => self.brightness = self.brightness.saturating_sub(1).max(1),
+            Effect::Menu(Action::ToggleSound) => self.muted = !self.muted,
+            // Nothing to calibrate, and new mazes come as `Effect::Generate`
+            Effect::Menu(_) => {}
+        }
+    }
+
+    fn maze_id(&self) -> MazeId {
+        MazeId::of(&self.maze, self.game.level(), self.algorithm)
+    }
+
+    /// Same as `start_maze` in the firmware, with the carving done at once instead of by `carve`.
+    fn generate(&mut self, request: &Generate) {
+        if let Some(maze) = self.imported.take() {
+            self.algorithm = maze.algorithm;
+            self.maze = maze;
+        } else {
+            if request.from_end {
+                self.maze.start = self.maze.end;
+            }
+            let seed = request.seed.unwrap_or_else(|| next_seed(self.maze.seed));
+            let mut generator = Generator::with_seed(self.algorithm, seed);
+            Difficulty::for_level(request.level).generate(&mut generator, &mut self.maze, || {});
+        }
+        self.ball = Ball::from_point(&self.maze.start, self.maze.cell_size());
+        display::draw_maze(&mut self.framebuffer, &self.maze);
+        display::draw_ball(&mut self.framebuffer, &self.ball);
+
+        let id = self.maze_id();
+        let best = self.records.iter().find(|&&(maze, _)| maze == id).map(|&(_, ticks)| ticks);
+        let shortest = self.maze.solve().map_or(0, |path| path.len());
+        self.handle(game::Event::Generated { shortest, best });
+    }
+
+    /// Same as the input task of the firmware, with `pressed` for a button press since the last one.
+    fn input(&mut self, dx: i16, dy: i16, pressed: Option<game::Event>) {
+        let step = self.stick.update(dx, dy);
+        if let Some(event) = pressed.or(step.map(game::Event::Step)) {
+            self.handle(event);
+        }
+        if let Some(effect) = self.game.tick() {
+            self.effect(effect);
+        }
+        if !self.game.ball_moves() {
+            return;
+        }
+
+        let ball = self.ball.moved(&self.maze, dx, dy);
+        if ball == self.ball {
+            return;
+        }
+        let cell_size = self.maze.cell_size();
+        let (from, to) = (self.ball.to_point(cell_size), ball.to_point(cell_size));
+        display::erase_ball(&mut self.framebuffer, &self.maze, &self.ball);
+        display::draw_ball(&mut self.framebuffer, &ball);
+        self.ball = ball;
+        if to != from {
+            self.handle(game::Event::Moved);
+            if to == self.maze.end {
+                self.handle(game::Event::ReachedEnd);
+            }
+        }
+    }
+
+    /// A short press of the button.
+    fn press(&self) -> game::Event {
+        let at_end = self.ball.to_point(self.maze.cell_size()) == self.maze.end;
+        game::Event::Pressed { at_end }
+    }
+
+    /// What the OLED shows, a line each.
+    fn oled(&self) -> Vec<String> {
+        let game = &self.game;
+        let mut lines = Vec::new();
+        for line in 0..hud::LINES {
+            let mut text = String::new();
+            let more = if game.state() == State::Menu {
+                let values = menu::Values {
+                    level: game.level(),
+                    algorithm: self.algorithm,
+                    brightness: self.brightness,
+                    muted: self.muted,
+                    dimensions: self.maze.dimensions,
+                    seed: self.maze.seed,
+                };
+                game.menu().write_line(&values, line, &mut text)
+            } else {
+                let time = game.ticks_left().unwrap_or_else(|| game.playing_ticks());
+                let status = Status {
+                    level: game.level(),
+                    seed: self.maze.seed,
+                    muted: self.muted,
+                    elapsed: time / game::TICKS_PER_SECOND,
+                    moves: Some(game.moves()),
+                    efficiency: game.efficiency(),
+                    best: game.best_ticks().map(|best| best / game::TICKS_PER_SECOND),
+                    message: game.state().message(),
+                };
+                status.write_line(line, &mut text)
+            };
+            if !more.unwrap_or(false) {
+                break;
+            }
+            lines.push(text);
+        }
+        lines
+    }
+}
+
+/// Keys held down, as the time they were last seen.
+#[derive(Default)]
+struct Tilt {
+    left: Option<Instant>,
+    right: Option<Instant>,
+    up: Option<Instant>,
+    down: Option<Instant>,
+}
+
+impl Tilt {
+    fn key(&mut self, code: KeyCode) -> Option<&mut Option<Instant>> {
+        match code {
+            KeyCode::Left | KeyCode::Char('a') => Some(&mut self.left),
+            KeyCode::Right | KeyCode::Char('d') => Some(&mut self.right),
+            KeyCode::Up | KeyCode::Char('w') => Some(&mut self.up),
+            KeyCode::Down | KeyCode::Char('s') => Some(&mut self.down),
+            _ => None,
+        }
+    }
+
+    /// The move of the ball for this input period, like the input task reads off the joystick.
+    fn delta(&self, now: Instant) -> (i16, i16) {
+        let held = |key: Option<Instant>| key.is_some_and(|seen| now - seen < KEY_HOLD) as i16;
+        (
+            TILT * (held(self.right) - held(self.left)),
+            TILT * (held(self.down) - held(self.up)),
+        )
+    }
+}
+
+/// Puts the terminal back the way it was, even after a panic.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/// Bytes of `text`, two hex digits each, as the `export` command prints them.
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.len() & 1 != 0 {
+        return None;
+    }
+    (0 .. text.len()).step_by(2).map(|at| u8::from_str_radix(text.get(at .. at + 2)?, 16).ok()).collect()
+}
+
+/// The seed and the mode given on the command line.
+fn arguments() -> Result<(u64, Mode, Option<Maze>), String> {
+    let mut seed = None;
+    let mut mode = Mode::Free;
+    let mut imported = None;
+    for arg in env::args().skip(1) {
+        // Same syntax as the `mode`, `seed` and `export` commands of the console
+        match (Command::parse(&format!("mode {}", arg)), Command::parse(&format!("seed {}", arg))) {
+            (Ok(Some(Command::Mode(Some(given)))), _) => mode = given,
+            (_, Ok(Some(Command::Seed(Some(given))))) => seed = Some(given),
+            _ => match hex_bytes(&arg) {
+                Some(bytes) => {
+                    let maze = Maze::from_bytes(&bytes).map_err(|error| format!("can't import {}: {}", arg, error))?;
+                    seed = Some(maze.seed);
+                    imported = Some(maze);
+                }
+                None => return Err(format!("neither a seed, a mode nor an exported maze: {}", arg)),
+            },
+        }
+    }
+    let seed = seed.unwrap_or_else(|| {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        next_seed(time.as_nanos() as u64)
+    });
+    Ok((seed, mode, imported))
+}
+
+fn run(seed: u64, mode: Mode, imported: Option<Maze>) -> io::Result<()> {
+    let (columns, lines) = terminal::size()?;
+    if columns < screen::COLUMNS || lines < screen::TOTAL_LINES {
+        return Err(io::Error::other(format!(
+            "the terminal needs to be at least {}x{}", screen::COLUMNS, screen::TOTAL_LINES,
+        )));
+    }
+
+    let _terminal = RawTerminal::enter()?;
+    let mut out = io::stdout();
+    let decoder = RefCell::new(Decoder::new());
+    let mut port = decoder::port(&decoder);
+    let mut scan = DmaScan::new();
+    let mut board = Board::new(seed, mode, imported);
+    let mut tilt = Tilt::default();
+    let mut next_input = Instant::now();
+    let mut next_frame = next_input;
+    loop {
+        let mut pressed = None;
+        while event::poll(next_input.saturating_duration_since(Instant::now()))? {
+            let KeyEvent { code, modifiers, kind, .. } = match event::read()? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            if let Some(key) = tilt.key(code) {
+                *key = if kind == KeyEventKind::Release { None } else { Some(Instant::now()) };
+                continue;
+            }
+            if kind == KeyEventKind::Release {
+                continue;
+            }
+            match code {
+                KeyCode::Char(' ') | KeyCode::Enter => pressed = Some(board.press()),
+                KeyCode::Char('m') => pressed = Some(game::Event::LongPressed),
+                KeyCode::Char('p') => board.handle(game::Event::Pause),
+                KeyCode::Char('n') => board.handle(game::Event::NewMaze { level: None, seed: None }),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                _ => {}
+            }
+        }
+        next_input += INPUT_PERIOD;
+
+        let (dx, dy) = tilt.delta(Instant::now());
+        board.input(dx, dy, pressed);
+
+        if Instant::now() >= next_frame {
+            next_frame += FRAME_PERIOD;
+            // One whole scan of the panel, what the TIM15 task does over 128 interrupts
+            for _ in 0..ROWS * COLOUR_BITS as usize {
+                scan.latch(&mut port);
+                scan.shift(&board.framebuffer, &PORT_PALETTE, |row| decoder::transfer(&mut port, &LINES, row));
+            }
+            let decoder = decoder.borrow();
+            let status = match decoder.violation() {
+                Some(violation) => format!("protocol broken {} times, first: {}", decoder.violations(), violation),
+                None => format!(
+                    "{} {}x{} {}  arrows: tilt  space: button  m: menu  p: pause  n: new maze  q: quit",
+                    board.game.mode().name(), board.maze.dimensions.width, board.maze.dimensions.height,
+                    board.algorithm.name(),
+                ),
+            };
+            screen::draw(&mut out, decoder.frame(), &board.oled(), &status)?;
+        }
+    }
+}
+
+fn main() {
+    let result = arguments().and_then(|(seed, mode, imported)| run(seed, mode, imported).map_err(|error| error.to_string()));
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
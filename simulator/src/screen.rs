@@ -0,0 +1,49 @@
+//! Draws the panel in a terminal, two pixels per character.
+
+use maze_core::decoder::Frame;
+use maze_core::framebuffer::{HEIGHT, WIDTH};
+use maze_core::hud;
+use maze_core::palette::{Levels, MAX_LEVEL};
+use crossterm::cursor::MoveTo;
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::QueueableCommand;
+use std::io::{self, Write};
+
+/// Columns and lines of the terminal taken by the panel.
+pub const COLUMNS: u16 = WIDTH as u16;
+pub const LINES: u16 = HEIGHT as u16 / 2;
+/// Lines taken by everything, with the text of the OLED and a status line under the panel.
+pub const TOTAL_LINES: u16 = LINES + hud::LINES as u16 + 1;
+
+/// Terminal colour for the levels of a pixel, undoing the gamma of `Levels::shade`.
+fn color(levels: Levels) -> Color {
+    let channel = |level: u8| (255.0 * (level as f32 / MAX_LEVEL as f32).sqrt()).round() as u8;
+    Color::Rgb { r: channel(levels.r), g: channel(levels.g), b: channel(levels.b) }
+}
+
+/// Draws `frame`, the lines of the OLED below it, and `status` on the last line.
+pub fn draw<W: Write>(out: &mut W, frame: &Frame, oled: &[String], status: &str) -> io::Result<()> {
+    for line in 0..LINES {
+        out.queue(MoveTo(0, line))?;
+        let mut current = None;
+        let (top, bottom) = (&frame[line as usize * 2], &frame[line as usize * 2 + 1]);
+        for pixels in top.iter().copied().zip(bottom.iter().copied()) {
+            // Upper half block: the foreground is the top pixel, the background the bottom one
+            if current != Some(pixels) {
+                out.queue(SetForegroundColor(color(pixels.0)))?;
+                out.queue(SetBackgroundColor(color(pixels.1)))?;
+                current = Some(pixels);
+            }
+            out.queue(Print('▀'))?;
+        }
+        out.queue(ResetColor)?;
+    }
+    for line in 0..hud::LINES {
+        let text = oled.get(line).map_or("", String::as_str);
+        out.queue(MoveTo(0, LINES + line as u16))?;
+        out.queue(Print(format!("{:<width$}", text, width = COLUMNS as usize)))?;
+    }
+    out.queue(MoveTo(0, TOTAL_LINES - 1))?;
+    out.queue(Print(format!("{:<width$}", status, width = COLUMNS as usize)))?;
+    out.flush()
+}
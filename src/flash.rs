@@ -0,0 +1,111 @@
+use crate::hal::stm32;
+use maze_core::store::Flash;
+use core::ptr;
+
+/// Erase granularity of the STM32F051.
+const PAGE_SIZE: u32 = 1024;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+extern "C" {
+    // The STORAGE region of memory.x
+    static _storage_start: u8;
+    static _storage_end: u8;
+}
+
+#[derive(Debug)]
+pub enum FlashError {
+    Programming,
+    WriteProtected,
+}
+
+/// The pages reserved for the settings at the end of the internal flash.
+///
+/// The CPU stalls while the flash is busy, so the display flickers for the
+/// few tens of milliseconds an erase takes.
+pub struct InternalFlash {
+    flash: stm32::FLASH,
+}
+
+impl InternalFlash {
+    pub fn new(flash: stm32::FLASH) -> InternalFlash {
+        InternalFlash { flash }
+    }
+
+    fn start() -> u32 {
+        unsafe { &_storage_start as *const u8 as u32 }
+    }
+
+    fn unlock(&mut self) {
+        if self.flash.cr.read().lock().bit_is_set() {
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY1));
+            self.flash.keyr.write(|w| w.fkeyr().bits(KEY2));
+        }
+    }
+
+    fn lock(&mut self) {
+        self.flash.cr.modify(|_, w| w.lock().set_bit());
+    }
+
+    fn wait(&mut self) -> Result<(), FlashError> {
+        while self.flash.sr.read().bsy().bit_is_set() {}
+        let sr = self.flash.sr.read();
+        // The flags are cleared by writing 1 to them
+        self.flash.sr.write(|w| w.eop().set_bit().pgerr().set_bit().wrprt().set_bit());
+        if sr.wrprt().bit_is_set() {
+            Err(FlashError::WriteProtected)
+        } else if sr.pgerr().bit_is_set() {
+            Err(FlashError::Programming)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Flash for InternalFlash {
+    type Error = FlashError;
+
+    fn page_size(&self) -> u32 {
+        PAGE_SIZE
+    }
+
+    fn pages(&self) -> u16 {
+        let end = unsafe { &_storage_end as *const u8 as u32 };
+        ((end - InternalFlash::start()) / PAGE_SIZE) as u16
+    }
+
+    fn read(&self, offset: u32, buffer: &mut [u8]) {
+        let address = (InternalFlash::start() + offset) as *const u8;
+        for (index, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile(address.add(index)) };
+        }
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.pg().set_bit());
+        let address = (InternalFlash::start() + offset) as *mut u16;
+        let mut result = Ok(());
+        for (index, halfword) in data.chunks(2).enumerate() {
+            unsafe { ptr::write_volatile(address.add(index), u16::from_le_bytes([halfword[0], halfword[1]])) };
+            result = self.wait();
+            if result.is_err() {
+                break;
+            }
+        }
+        self.flash.cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        result
+    }
+
+    fn erase(&mut self, page: u16) -> Result<(), FlashError> {
+        self.unlock();
+        self.flash.cr.modify(|_, w| w.per().set_bit());
+        self.flash.ar.write(|w| w.far().bits(InternalFlash::start() + page as u32 * PAGE_SIZE));
+        self.flash.cr.modify(|_, w| w.strt().set_bit());
+        let result = self.wait();
+        self.flash.cr.modify(|_, w| w.per().clear_bit());
+        self.lock();
+        result
+    }
+}
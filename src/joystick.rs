@@ -22,4 +22,19 @@ impl<X, Y, BTN> Joystick<X, Y, BTN>
             mid_x, mid_y
         }
     }
-}
\ No newline at end of file
+
+    /// Takes the current position of the stick as its centre, and returns it.
+    pub fn calibrate<ADC, A>(&mut self, adc: &mut A) -> (u16, u16)
+    where X: Channel<ADC>,
+          Y: Channel<ADC>,
+          A: OneShot<ADC, u16, X> + OneShot<ADC, u16, Y>,
+    {
+        if let Ok(x) = adc.read(&mut self.axis_x) {
+            self.mid_x = x;
+        }
+        if let Ok(y) = adc.read(&mut self.axis_y) {
+            self.mid_y = y;
+        }
+        (self.mid_x, self.mid_y)
+    }
+}
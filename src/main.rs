@@ -1,12 +1,11 @@
 #![no_main]
 #![no_std]
 
-mod maze;
-mod hub;
-mod display;
-mod ball;
+mod flash;
 mod joystick;
-mod cell;
+mod oled;
+mod panel_dma;
+mod shared;
 mod sounds;
 
 use panic_halt as _;
@@ -15,10 +14,9 @@ use stm32f0xx_hal as hal;
 use crate::hal::{prelude::*, stm32, serial, delay::Delay, i2c::{I2c}, gpio::*};
 
 
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
 use core::fmt::Write;
 use crate::hal::dac::*;
-use crate::maze::Point;
 use core::cell::RefCell;
 use core::ops::DerefMut;
 use stm32f0::stm32f0x1::Interrupt;
@@ -34,37 +32,120 @@ use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{Circle, Rectangle, Triangle};
 use joystick::Joystick;
-use crate::display::PWMFrequency;
-use crate::ball::Ball;
+use shared::Shared;
+use maze_core::{ball, display, hub, maze};
+use maze_core::algorithms::{next_seed, Algorithm, Generator, MazeAlgorithm};
+use maze_core::brightness::{self, Dimmer, MAX_BRIGHTNESS};
+use maze_core::console::{self, Command, LineBuffer};
+use maze_core::difficulty::Difficulty;
+use maze_core::entropy::Entropy;
+use maze_core::dma::{DmaScan, PortPalette};
+use maze_core::framebuffer::{Framebuffer, ROWS};
+use maze_core::game::{self, Effect, Event, Game, Generate, State};
+use maze_core::hud::Status;
+use maze_core::menu::{self, Action, Button, Menu, Press, Stick};
+use maze_core::palette::MAX_LEVEL;
+use maze_core::records::{self, MazeId};
+use maze_core::settings::Settings;
+use maze_core::store::Store;
 use cortex_m_semihosting::debug::Exception::InternalError;
 
+/// The panel of the README, rows picked by a shift register on A to C.
+#[cfg(not(feature = "hub75"))]
+type PanelPort = hub::HUBPort<
+    hub::Unused,
+    gpiob::PB0<Output<PushPull>>,
+    gpiob::PB2<Output<PushPull>>,
+    gpiob::PB3<Output<PushPull>>,
+    gpiob::PB4<Output<PushPull>>,
+    gpiob::PB5<Output<PushPull>>,
+    gpioc::PC0<Output<PushPull>>,
+    gpioc::PC1<Output<PushPull>>,
+    gpioc::PC2<Output<PushPull>>,
+    gpioc::PC3<Output<PushPull>>,
+    gpioc::PC4<Output<PushPull>>,
+    gpioc::PC5<Output<PushPull>>
+>;
+
+/// A standard HUB75 panel, the number of the row on A to E, with D and E on PB8 and PB9.
+#[cfg(feature = "hub75")]
+type PanelPort = hub::HUB75Port<
+    hub::Unused,
+    gpiob::PB0<Output<PushPull>>,
+    gpiob::PB2<Output<PushPull>>,
+    gpiob::PB3<Output<PushPull>>,
+    gpiob::PB4<Output<PushPull>>,
+    gpiob::PB5<Output<PushPull>>,
+    gpiob::PB8<Output<PushPull>>,
+    gpiob::PB9<Output<PushPull>>,
+    gpioc::PC0<Output<PushPull>>,
+    gpioc::PC1<Output<PushPull>>,
+    gpioc::PC2<Output<PushPull>>,
+    gpioc::PC3<Output<PushPull>>,
+    gpioc::PC4<Output<PushPull>>,
+    gpioc::PC5<Output<PushPull>>
+>;
+
+/// How many times a second `input` runs, each one a tick of the game.
+const INPUT_RATE: u32 = game::TICKS_PER_SECOND;
+/// Roughly how many runs of `input` the carving of a maze is spread over, whatever its size.
+const CARVING_TICKS: u16 = 100;
+
+/// What the OLED shows.
+#[derive(Copy, Clone, PartialEq)]
+enum Screen {
+    Status(Status),
+    Menu(Menu, menu::Values),
+}
+
+/// `display::PALETTE` laid out for the data lines on GPIOC.
+static PORT_PALETTE: PortPalette = PortPalette::new(&display::PALETTE, &panel_dma::LINES);
+
 #[rtfm::app(device = stm32f0xx_hal::stm32, peripherals = true)]
 const APP: () = {
     struct Resources {
-        // A resource
-        maze: maze::Maze,
-        ball: ball::Ball,
-
-        #[init(0)]
-        current_row: u8,
-
-        #[init(false)]
-        mute: bool,
-
-        hub_port: hub::HUBPort<
-            gpiob::PB1<Output<PushPull>>,
-            gpiob::PB0<Output<PushPull>>,
-            gpiob::PB2<Output<PushPull>>,
-            gpiob::PB3<Output<PushPull>>,
-            gpiob::PB4<Output<PushPull>>,
-            gpiob::PB5<Output<PushPull>>,
-            gpioc::PC0<Output<PushPull>>,
-            gpioc::PC1<Output<PushPull>>,
-            gpioc::PC2<Output<PushPull>>,
-            gpioc::PC3<Output<PushPull>>,
-            gpioc::PC4<Output<PushPull>>,
-            gpioc::PC5<Output<PushPull>>
-        >,
+        /// Only changed by the tasks at the lowest priority, and the ball also by `input`.
+        maze: Shared<maze::Maze>,
+        ball: Shared<ball::Ball>,
+
+        /// Where `tick` got in the scan of the panel, and the rows going out by DMA.
+        #[init(DmaScan::new())]
+        scan: DmaScan,
+        panel_dma: panel_dma::PanelDma,
+        /// TIM15 reload value for the shortest bit plane, the longer ones are multiples of it.
+        scan_unit: u16,
+        /// How bright `tick` shows the panel, following `settings` and the ambient light.
+        dimmer: Dimmer,
+
+        /// What the panel shows, `tick` sends it out one row at a time.
+        #[init(Framebuffer::new())]
+        framebuffer: Framebuffer,
+
+        #[init(Algorithm::Backtracker)]
+        algorithm: Algorithm,
+        /// Carves the maze `carve` is working on, with the difficulty to finish it with.
+        generator: Generator,
+        #[init(None)]
+        carving: Option<Difficulty>,
+
+        /// Level, sound and calibration, as last saved to `store`.
+        /// The level picks the `Difficulty` of the next maze.
+        settings: Settings,
+        store: Store<flash::InternalFlash>,
+
+        /// What has been typed on the serial console so far.
+        #[init(LineBuffer::new())]
+        console_line: LineBuffer,
+
+        hub_port: PanelPort,
+
+        /// Where the game is at, `input` moves it on `INPUT_RATE` times a second.
+        /// The menu on the OLED is part of it, opened with a long press of the joystick button.
+        game: Game,
+        #[init(Button::new())]
+        button: Button,
+        #[init(Stick::new())]
+        stick: Stick,
 
         led: gpioc::PC8<Output<PushPull>>,
         delay: Delay,
@@ -83,6 +164,8 @@ const APP: () = {
             gpioa::PA1<Analog>,
             gpioa::PA3<Input<PullUp>>,
         >,
+        /// Optional light sensor for `autodim`, reading higher the brighter the room.
+        ambient_sensor: gpioa::PA5<Analog>,
         adc: hal::adc::Adc,
         sounds: sounds::SoundController,
         serial: serial::Serial<
@@ -90,7 +173,6 @@ const APP: () = {
             gpioa::PA9<Alternate<hal::gpio::AF1>>,
             gpioa::PA10<Alternate<hal::gpio::AF1>>,
         >,
-        exti: hal::stm32::EXTI,
     }
 
     #[init]
@@ -113,35 +195,60 @@ const APP: () = {
         let gpioc = _device.GPIOC.split(&mut rcc);
         let mut delay = Delay::new(_core.SYST, &rcc);
         let (
-            mut port,
+            port,
+            _panel_clock,
             mut led_green, mut led_blue,
             mut btn,
             mut buz,
             mut scl, mut sda,
             mut joystick_x, mut joystick_y, mut joystick_btn,
+            ambient_sensor,
             tx, rx,
         ) = cortex_m::interrupt::free(|cs| {
-            // Configure pins for SPI
-            (hub::HUBPort {
-                clock: gpiob.pb1.into_push_pull_output_hs(cs),
+            let data_upper = hub::HUBDataPort {
+                r: gpioc.pc0.into_push_pull_output_hs(cs),
+                g: gpioc.pc1.into_push_pull_output_hs(cs),
+                b: gpioc.pc2.into_push_pull_output_hs(cs),
+            };
+            let data_lower = hub::HUBDataPort {
+                r: gpioc.pc3.into_push_pull_output_hs(cs),
+                g: gpioc.pc4.into_push_pull_output_hs(cs),
+                b: gpioc.pc5.into_push_pull_output_hs(cs),
+            };
+            #[cfg(not(feature = "hub75"))]
+            let mut port = hub::HUBPort {
+                // Driven by TIM1, see panel_dma
+                clock: hub::Unused,
                 output_enabled: gpiob.pb0.into_push_pull_output_hs(cs),
                 latch: gpiob.pb2.into_push_pull_output_hs(cs),
-                data_upper: hub::HUBDataPort {
-                    r: gpioc.pc0.into_push_pull_output_hs(cs),
-                    g: gpioc.pc1.into_push_pull_output_hs(cs),
-                    b: gpioc.pc2.into_push_pull_output_hs(cs),
-                },
-                data_lower: hub::HUBDataPort {
-                    r: gpioc.pc3.into_push_pull_output_hs(cs),
-                    g: gpioc.pc4.into_push_pull_output_hs(cs),
-                    b: gpioc.pc5.into_push_pull_output_hs(cs),
+                data_upper,
+                data_lower,
+                row_selection: hub::HUBRowSelectionPort::new(
+                    gpiob.pb3.into_push_pull_output_hs(cs), // LCK
+                    gpiob.pb4.into_push_pull_output_hs(cs), // BK
+                    gpiob.pb5.into_push_pull_output_hs(cs), // DIN
+                ),
+            };
+            // keep row selection port enabled
+            #[cfg(not(feature = "hub75"))]
+            port.row_selection.b.set_low().ok();
+            #[cfg(feature = "hub75")]
+            let port = hub::HUB75Port {
+                clock: hub::Unused,
+                output_enabled: gpiob.pb0.into_push_pull_output_hs(cs),
+                latch: gpiob.pb2.into_push_pull_output_hs(cs),
+                data_upper,
+                data_lower,
+                address: hub::HUBAddressPort {
+                    a: gpiob.pb3.into_push_pull_output_hs(cs),
+                    b: gpiob.pb4.into_push_pull_output_hs(cs),
+                    c: gpiob.pb5.into_push_pull_output_hs(cs),
+                    d: gpiob.pb8.into_push_pull_output_hs(cs),
+                    e: gpiob.pb9.into_push_pull_output_hs(cs),
                 },
-                row_selection: hub::HUBRowSelectionPort {
-                    a: gpiob.pb3.into_push_pull_output_hs(cs), // LCK
-                    b: gpiob.pb4.into_push_pull_output_hs(cs), // BK
-                    c: gpiob.pb5.into_push_pull_output_hs(cs), // DIN
-                }
-            },
+            };
+            (port,
+             gpiob.pb1.into_alternate_af2(cs),
 
              gpioc.pc9.into_push_pull_output(cs),
              gpioc.pc8.into_push_pull_output(cs),
@@ -152,6 +259,7 @@ const APP: () = {
              gpioa.pa2.into_analog(cs),
              gpioa.pa1.into_analog(cs),
              gpioa.pa3.into_pull_up_input(cs),
+             gpioa.pa5.into_analog(cs),
              gpioa.pa9.into_alternate_af1(cs),
              gpioa.pa10.into_alternate_af1(cs),
             )
@@ -159,58 +267,92 @@ const APP: () = {
 
         let mut i2c = I2c::i2c1(_device.I2C1, (scl, sda), 100.khz(), &mut rcc);
         let mut serial = serial::Serial::usart1(_device.USART1, (tx, rx), 115_200.bps(), &mut rcc);
+        serial.listen(serial::Event::Rxne);
         let mut display: GraphicsMode<_> = Builder::new().connect_i2c(i2c).into();
-        //display.init();
-        //display.clear();
+        // Without an OLED the writes fail and it's just left out
+        display.init().ok();
+        display.clear();
+        display.flush().ok();
 
 
         let mut adc = hal::adc::Adc::new(_device.ADC, &mut rcc);
 
 
         let mut dac = hal::dac::dac(_device.DAC, buz, &mut rcc);
-        let sounds = sounds::SoundController::new(dac);
+        let mut sounds = sounds::SoundController::new(dac);
+
+        let store = Store::mount(flash::InternalFlash::new(_device.FLASH));
+        let settings = Settings::load(&store);
+        sounds.set_muted(settings.muted);
 
         led_blue.set_high().ok();
 
         led_blue.set_low().ok();
-        // keep row selection port enabled
-        port.row_selection.b.set_low().ok();
 
         // Setting up timer for display refresh
-        // Why 60 * 32? 120 Hertz is the refresh rate for high end screens, and we have 32 rows
-        // This value needs to be as low as possible, otherwise CPU utilization rate would be too high
-        // For us to do anything else useful.
-        let mut timer = hal::timers::Timer::tim15(_device.TIM15, hal::time::Hertz(120 * 32), &mut rcc);
+        // Each row is sent once per bit plane and shown for 1, 2, 4 and 8 units,
+        // so a frame takes ROWS * 15 units. 60 Hertz keeps it from flickering while leaving
+        // the CPU time to do anything else useful. The DMA shifts the next row out while
+        // the current one is lit, which takes a little under one unit.
+        let mut timer = hal::timers::Timer::tim15(_device.TIM15, hal::time::Hertz(60 * ROWS as u32 * MAX_LEVEL as u32), &mut rcc);
         timer.listen(hal::timers::Event::TimeOut);
-
-        let mut timer_input = hal::timers::Timer::tim14(_device.TIM14, hal::time::Hertz(100), &mut rcc);
+        let scan_unit = unsafe { (*stm32::TIM15::ptr()).arr.read().bits() as u16 };
+        // Compare channel 1 ends each plane early to dim the panel
+        unsafe { (*stm32::TIM15::ptr()).dier.modify(|_, w| w.cc1ie().set_bit()) };
+        let panel_dma = panel_dma::PanelDma::new(_device.TIM1);
+        let mut dimmer = Dimmer::new();
+        dimmer.brightness = settings.brightness;
+        dimmer.automatic = settings.auto_brightness;
+
+        let mut timer_input = hal::timers::Timer::tim14(_device.TIM14, hal::time::Hertz(INPUT_RATE), &mut rcc);
         timer_input.listen(hal::timers::Event::TimeOut);
 
         let mut nvic = _core.NVIC;
         unsafe {
             cortex_m::peripheral::NVIC::unmask(Interrupt::TIM15);
             cortex_m::peripheral::NVIC::unmask(Interrupt::TIM14);
-            cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI2_3);
+            cortex_m::peripheral::NVIC::unmask(Interrupt::USART1);
         }
 
-        let exti = _device.EXTI;
-        let syscfg = _device.SYSCFG;
-
-        // Do nothing to select pa3
-        // syscfg.exticr1.modify(|_, w| unsafe { w.exti1().bits(1) });
-
-        // Enable EXTI3
-        exti.imr.modify(|_, w| w.mr3().set_bit());
-
-        // Rising edge trigger
-        exti.rtsr.modify(|_, w| w.tr3().set_bit());
+        // The joystick button on PA3 is polled by `input`, which tells short presses from long ones
+        let (joystick_mid_x, joystick_mid_y) = match settings.joystick_centre {
+            Some(centre) => centre,
+            None => (adc.read(&mut joystick_x).unwrap(), adc.read(&mut joystick_y).unwrap()),
+        };
+
+        // Seed the first maze from the noise on the temperature sensor and the joystick,
+        // together with how far the display timer got while the ADC was converting.
+        let mut entropy = Entropy::new();
+        let mut vtemp = hal::adc::VTemp::new();
+        vtemp.enable(&mut adc);
+        while entropy.samples() < 96 {
+            let temperature: u16 = adc.read(&mut vtemp).unwrap();
+            let x: u16 = adc.read(&mut joystick_x).unwrap();
+            let y: u16 = adc.read(&mut joystick_y).unwrap();
+            let jitter = unsafe { (*stm32::TIM15::ptr()).cnt.read().bits() as u16 };
+            entropy.push(temperature);
+            entropy.push(x);
+            entropy.push(y);
+            entropy.push(jitter);
+        }
+        vtemp.disable(&mut adc);
+        let mut maze = maze::Maze::new();
+        // Pick up the maze that was being played before the reset
+        maze.seed = settings.seed.unwrap_or_else(|| entropy.seed());
+        let mut game = Game::new(settings.level);
+        game.set_auto_finish(settings.auto_finish);
+        game.set_mode(settings.mode);
 
-        let joystick_mid_x: u16 = adc.read(&mut joystick_x).unwrap();
-        let joystick_mid_y: u16 = adc.read(&mut joystick_y).unwrap();
         init::LateResources {
+            game,
+            generator: Generator::new(Algorithm::Backtracker),
             hub_port: port,
-            maze: maze::Maze::new(),
-            ball: ball::Ball::new(),
+            scan_unit,
+            panel_dma,
+            dimmer,
+            ambient_sensor,
+            maze: Shared::new(maze),
+            ball: Shared::new(ball::Ball::new()),
             led: led_blue,
             delay,
             display,
@@ -224,153 +366,487 @@ const APP: () = {
                 joystick_mid_y,
             ),
             serial,
-            exti
+            settings,
+            store,
         }
     }
 
-    #[task(binds = TIM15, resources=[current_row, &maze, &ball, hub_port], priority=10)]
+    #[task(binds = TIM15, resources=[scan, panel_dma, &scan_unit, dimmer, framebuffer, hub_port], priority=10)]
     fn tick (ctx: tick::Context) {
-        let current_row: &mut u8 = ctx.resources.current_row;
-        let maze: &maze::Maze = ctx.resources.maze;
         let port = ctx.resources.hub_port;
-        if *current_row == 32 {
-            *current_row = 0;
+        let tim15 = unsafe { stm32::Peripherals::steal().TIM15 };
+        let status = tim15.sr.read();
+        if status.cc1if().bit_is_set() {
+            // The plane has been lit long enough for the brightness
+            hub::PanelDriver::blank(port);
+            tim15.sr.modify(|_, w| w.cc1if().clear_bit());
+        }
+        if status.uif().bit_is_clear() {
+            return;
         }
-        display::draw_row(port, maze, ctx.resources.ball, *current_row);
-        *current_row += 1;
 
+        // The row is normally in well before the end of even the shortest plane. If it isn't,
+        // the panel stays as it is for one more unit rather than this waiting for the DMA.
+        let panel_dma = ctx.resources.panel_dma;
+        let plane = if panel_dma.busy() {
+            None
+        } else {
+            let scan = ctx.resources.scan;
+            // Nothing to latch on the first interrupt, the first row only starts going out here
+            let plane = scan.latch(port);
+            scan.shift(ctx.resources.framebuffer, &PORT_PALETTE, |row| panel_dma.start(row));
+            plane
+        };
+
+        // Keep the plane lit for its weight, counted from when it was latched.
+        // At full brightness the compare is past the reload and never matches.
+        let period = (*ctx.resources.scan_unit as u32 + 1) << plane.unwrap_or(0);
+        let lit = brightness::lit_ticks(ctx.resources.dimmer.level(), period);
         unsafe {
-            stm32::Peripherals::steal().TIM15.sr.write(|w| w.uif().clear_bit());
+            tim15.arr.write(|w| w.bits(period - 1));
+            tim15.ccr1.write(|w| w.bits(lit));
+            tim15.cnt.write(|w| w.bits(0));
+            tim15.sr.write(|w| w.uif().clear_bit().cc1if().clear_bit());
         }
     }
 
-    #[task(binds = TIM14, resources=[&ball, hub_port, adc, joystick, &maze, serial], priority=5)]
-    fn input (ctx: input::Context) {
+    #[task(binds = TIM14, resources=[&ball, framebuffer, adc, joystick, ambient_sensor, dimmer, &maze, game, button, stick], spawn=[effect, carve], priority=5)]
+    fn input (mut ctx: input::Context) {
+        unsafe {
+            stm32::Peripherals::steal().TIM14.sr.write(|w| w.uif().clear_bit());
+        }
+
         let valx: u16 = ctx.resources.adc.read(&mut ctx.resources.joystick.axis_x).unwrap();
         let valy: u16 = ctx.resources.adc.read(&mut ctx.resources.joystick.axis_y).unwrap();
-        let ball = ctx.resources.ball;
+        let ambient: u16 = ctx.resources.adc.read(ctx.resources.ambient_sensor).unwrap();
+        ctx.resources.dimmer.lock(|dimmer| dimmer.sense(ambient));
+        let maze = ctx.resources.maze.get();
+        let ball = ctx.resources.ball.get();
         let mut valx: i16 = ctx.resources.joystick.mid_x as i16 - valx as i16;
         let mut valy: i16 = valy as i16 - ctx.resources.joystick.mid_y as i16;
 
         valx /= 128;
         valy /= 128;
 
-        let mut newx: i16 = ball.x as i16 + valx;
-        let mut newy: i16 = ball.y as i16 + valy;
-        if newx < 0 { newx = 0; }
-        if newy < 0 { newy = 0; }
-        if newx >= 128 * PWMFrequency as i16 {
-            newx = 128 * PWMFrequency as i16 - 1;
+        let step = ctx.resources.stick.update(valx, valy);
+        let pressed = ctx.resources.joystick.button.is_low().unwrap_or(false);
+        let at_end = ball.to_point(maze.cell_size()) == maze.end;
+        let event = match ctx.resources.button.update(pressed) {
+            Some(Press::Short) => Some(Event::Pressed { at_end }),
+            Some(Press::Long) => Some(Event::LongPressed),
+            None => step.map(Event::Step),
+        };
+        let game = ctx.resources.game;
+        let effects = [event.and_then(|event| game.handle(event)), game.tick()];
+        for &effect in effects.iter().flatten() {
+            ctx.spawn.effect(effect).ok();
         }
-        if newy >= 64 * PWMFrequency as i16 {
-            newy = 64 * PWMFrequency as i16 - 1;
+        if game.state() == State::Generating {
+            // Still queued if the last part isn't carved yet
+            ctx.spawn.carve().ok();
         }
-
-        // move on the x direction first
-        let mut ball_after_screen_pos: Ball = Ball { x: newx as u16, y: ball.y };
-
-        let mut cell = crate::cell::Cell::of_ball_point(ball);
-        if !cell.contains(&ball_after_screen_pos) {
-            //write!(ctx.resources.serial, " not containing ");
-            // Entered a new cell
-            let new_cell = crate::cell::Cell::of_ball_point(&ball_after_screen_pos);
-             if !ctx.resources.maze.connected(&cell, &new_cell) {
-                cell.bound_x(&mut ball_after_screen_pos);
-            }
+        if !game.ball_moves() {
+            return;
         }
 
-        ball_after_screen_pos.y = newy as u16;
-
-        cell = crate::cell::Cell::of_ball_point(ball);
-        if !cell.contains(&ball_after_screen_pos) {
-            // Entered a new cell
-            let new_cell = crate::cell::Cell::of_ball_point(&ball_after_screen_pos);
-            if !ctx.resources.maze.connected(&cell, &new_cell) {
-                cell.bound_y(&mut ball_after_screen_pos);
-            }
+        let ball_after_screen_pos = ball.moved(maze, valx, valy);
+        if ball_after_screen_pos == *ball {
+            return;
         }
-
-        //write!(ctx.resources.serial, "             \r");
-        unsafe {
-            let ptr = ctx.resources.ball as *const ball::Ball as *mut ball::Ball;
-            let ball = &mut *ptr;
-            *ball = ball_after_screen_pos;
+        let cell = ball_after_screen_pos.to_point(maze.cell_size());
+        let events = [
+            Some(Event::Moved).filter(|_| cell != ball.to_point(maze.cell_size())),
+            Some(Event::ReachedEnd).filter(|_| !at_end && cell == maze.end),
+        ];
+        for &event in events.iter().flatten() {
+            if let Some(effect) = game.handle(event) {
+                ctx.spawn.effect(effect).ok();
+            }
         }
+        ctx.resources.framebuffer.lock(|framebuffer| {
+            display::erase_ball(framebuffer, maze, ball);
+            display::draw_ball(framebuffer, &ball_after_screen_pos);
+        });
 
-
-
-
-        unsafe {
-            stm32::Peripherals::steal().TIM14.sr.write(|w| w.uif().clear_bit());
-        }
+        // The tasks changing the maze put the ball back on its start too, but only once
+        // the game is generating, and then the ball doesn't move
+        unsafe { *ctx.resources.ball.get_mut() = ball_after_screen_pos };
     }
 
-    #[task(binds = EXTI2_3, resources=[exti, &ball, &maze, delay, sounds])]
-    fn joystick_pressed(ctx: joystick_pressed::Context) {
-        let mut maze_generator = maze::MazeGenerator::new();
-        let point = ctx.resources.ball.to_point();
+    /// Does what the game asked for.
+    ///
+    /// A tune holds it up for a while, so there's room for a few more effects meanwhile.
+    /// Should a new maze still get lost, `carve` starts it from the game.
+    #[task(capacity = 6, resources=[&maze, &ball, game, delay, sounds, algorithm, generator, carving, settings, store, adc, joystick, dimmer, framebuffer])]
+    fn effect(ctx: effect::Context, effect: Effect) {
+        let maze = ctx.resources.maze;
+        let ball = ctx.resources.ball;
+        let settings = ctx.resources.settings;
+        let before = *settings;
         let delay = ctx.resources.delay;
-        if point == ctx.resources.maze.end {
-            ctx.resources.sounds.enable(30);
-            unsafe {
-                let ptr = ctx.resources.maze as *const maze::Maze as *mut maze::Maze;
-                let maze = &mut *ptr;
-
-                maze.start = maze.end;
-                maze_generator.generate(maze, || {
-                    delay.delay_ms(2_u8);
-                });
-
-                let ball_ptr = ctx.resources.ball as *const ball::Ball as *mut ball::Ball;
-                let ball = &mut *ball_ptr;
-
-                *ball = ball::Ball::from_point(&(maze.start));
+        let sounds = &mut *ctx.resources.sounds;
+        let mut framebuffer = ctx.resources.framebuffer;
+        let mut dimmer = ctx.resources.dimmer;
+        match effect {
+            Effect::Generate(request) => {
+                settings.level = request.level;
+                let generator = &mut *ctx.resources.generator;
+                *ctx.resources.carving = Some(start_maze(maze, ball, &mut framebuffer, generator, *ctx.resources.algorithm, &request));
             }
-            ctx.resources.sounds.disable();
-            delay.delay_ms(70u8);
-            for i in 0 .. 2 {
-                delay.delay_ms(30u8);
-                ctx.resources.sounds.enable(15);
-                delay.delay_ms(30u8);
-                ctx.resources.sounds.disable();
+            Effect::Celebrate { record } => {
+                if let Some(ticks) = record {
+                    let id = MazeId::of(maze.get(), settings.level, ctx.resources.generator.algorithm());
+                    records::keep(&mut *ctx.resources.store, id, ticks).ok();
+                }
+                sounds.enable(30);
+                delay.delay_ms(100u8);
+                sounds.disable();
+                delay.delay_ms(70u8);
+                for _ in 0 .. 2 {
+                    delay.delay_ms(30u8);
+                    sounds.enable(15);
+                    delay.delay_ms(30u8);
+                    sounds.disable();
+                }
+            }
+            Effect::Beep => {
+                sounds.enable(80);
+                delay.delay_ms(100u16);
+                sounds.disable();
+            }
+            // New mazes come as `Effect::Generate`
+            Effect::Menu(Action::Resume) | Effect::Menu(Action::NewGame) | Effect::Menu(Action::Level(_)) => {}
+            Effect::Menu(Action::Algorithm(algorithm)) => {
+                *ctx.resources.algorithm = algorithm;
+            }
+            Effect::Menu(action @ Action::Brighter) | Effect::Menu(action @ Action::Dimmer) => {
+                settings.brightness = match action {
+                    Action::Brighter => (settings.brightness + 1).min(MAX_BRIGHTNESS),
+                    _ => settings.brightness.saturating_sub(1).max(1),
+                };
+                let brightness = settings.brightness;
+                dimmer.lock(|dimmer| dimmer.brightness = brightness);
+            }
+            Effect::Menu(Action::ToggleSound) => {
+                sounds.set_muted(!sounds.muted());
+                settings.muted = sounds.muted();
+            }
+            Effect::Menu(Action::Calibrate) => {
+                let mut adc = ctx.resources.adc;
+                let mut joystick = ctx.resources.joystick;
+                let centre = adc.lock(|adc| joystick.lock(|joystick| joystick.calibrate(adc)));
+                settings.joystick_centre = Some(centre);
             }
-        } else {
-            ctx.resources.sounds.enable(80);
-            delay.delay_ms(100u16);
-            ctx.resources.sounds.disable();
         }
-        // unsafe is ok here, idle is the only task requiring mutable access to maze.
-        // Needed because 0.5.1 version of cortex-m-rtfm does not support mixed resources access
-
-        ctx.resources.exti.pr.write(|w| w.pr3().set_bit());
+        // Erasing a page stalls the CPU, and the panel with it, so only changes get written
+        if *settings != before {
+            settings.save(&mut *ctx.resources.store).ok();
+        }
     }
 
-    #[idle(resources = [&maze, &ball])]
-    fn idle (ctx: idle::Context) -> ! {
-        let mut maze_generator = maze::MazeGenerator::new();
-
-        // unsafe is ok here, idle is the only task requiring mutable access to maze.
-        // Needed because 0.5.1 version of cortex-m-rtfm does not support mixed resources access
-        unsafe {
-            let ptr = ctx.resources.maze as *const maze::Maze as *mut maze::Maze;
-            let maze = &mut *ptr;
-
-            maze_generator.generate(maze, ||{});
-
-            let ball_ptr = ctx.resources.ball as *const ball::Ball as *mut ball::Ball;
-            let ball = &mut *ball_ptr;
+    /// Carves the next part of the maze, `input` spawns it as long as the game is generating.
+    #[task(resources=[&maze, &ball, game, algorithm, generator, carving, settings, store, framebuffer])]
+    fn carve(ctx: carve::Context) {
+        let maze = ctx.resources.maze;
+        let mut game = ctx.resources.game;
+        let difficulty = match *ctx.resources.carving {
+            Some(difficulty) => difficulty,
+            // Tasks at this priority run in the order they were spawned, so the `effect`
+            // starting the maze has already run, unless its queue was full
+            None => match game.lock(|game| game.generating()) {
+                Some(request) => {
+                    ctx.resources.settings.level = request.level;
+                    let generator = &mut *ctx.resources.generator;
+                    let mut framebuffer = ctx.resources.framebuffer;
+                    *ctx.resources.carving = Some(start_maze(maze, ctx.resources.ball, &mut framebuffer, generator, *ctx.resources.algorithm, &request));
+                    return;
+                }
+                None => return,
+            },
+        };
+        let steps = maze.get().dimensions.cells() / CARVING_TICKS + 1;
+        let mut framebuffer = ctx.resources.framebuffer;
+        if carve_maze(maze, ctx.resources.ball, &mut framebuffer, ctx.resources.generator, difficulty, steps) {
+            *ctx.resources.carving = None;
+            let id = MazeId::of(maze.get(), game.lock(|game| game.level()), ctx.resources.generator.algorithm());
+            let generated = Event::Generated {
+                shortest: maze.get().solve().map_or(0, |path| path.len()),
+                best: records::best(ctx.resources.store, id),
+            };
+            game.lock(|game| game.handle(generated));
+        }
+    }
 
-            *ball = ball::Ball::from_point(&(maze.start));
+    #[idle(resources = [&maze, algorithm, settings, display, game], spawn = [effect])]
+    fn idle (mut ctx: idle::Context) -> ! {
+        // init put the boot seed into the maze
+        let booted = Event::Booted { seed: ctx.resources.maze.get().seed };
+        if let Some(effect) = ctx.resources.game.lock(|game| game.handle(booted)) {
+            ctx.spawn.effect(effect).ok();
         }
 
+        // The OLED is redrawn whenever what it shows changes. Sending it over I2C
+        // takes a while, but every task preempts idle, so the panel doesn't notice.
+        let mut shown: Option<Screen> = None;
         loop {
+            let (state, menu, level, time, best, moves, efficiency) = ctx.resources.game.lock(|game| {
+                let time = game.ticks_left().unwrap_or_else(|| game.playing_ticks());
+                (game.state(), *game.menu(), game.level(), time, game.best_ticks(), game.moves(), game.efficiency())
+            });
+            let settings = ctx.resources.settings.lock(|settings| *settings);
+            let seed = ctx.resources.maze.get().seed;
+            let screen = if state == State::Menu {
+                Screen::Menu(menu, menu::Values {
+                    level,
+                    algorithm: ctx.resources.algorithm.lock(|algorithm| *algorithm),
+                    brightness: settings.brightness,
+                    muted: settings.muted,
+                    dimensions: ctx.resources.maze.get().dimensions,
+                    seed,
+                })
+            } else {
+                Screen::Status(Status {
+                    level,
+                    seed,
+                    muted: settings.muted,
+                    elapsed: time / INPUT_RATE,
+                    moves: Some(moves),
+                    efficiency,
+                    best: best.map(|best| best / INPUT_RATE),
+                    message: state.message(),
+                })
+            };
+            if shown != Some(screen) {
+                let display = &mut *ctx.resources.display;
+                display.clear();
+                let mut oled = oled::Oled(display);
+                match screen {
+                    Screen::Status(status) => status.draw(&mut oled),
+                    Screen::Menu(menu, values) => menu.draw(&values, &mut oled),
+                }.ok();
+                display.flush().ok();
+                shown = Some(screen);
+            }
             wfi();
         }
     }
 
+    #[task(binds = USART1, resources=[serial, console_line, &maze, &ball, game, sounds, algorithm, generator, carving, settings, store, adc, joystick, dimmer, framebuffer])]
+    fn serial_received(ctx: serial_received::Context) {
+        let serial = ctx.resources.serial;
+        let line = ctx.resources.console_line;
+        let maze = ctx.resources.maze;
+        let ball = ctx.resources.ball;
+        let mut adc = ctx.resources.adc;
+        let mut joystick = ctx.resources.joystick;
+        let mut dimmer = ctx.resources.dimmer;
+        let settings = ctx.resources.settings;
+        let mut framebuffer = ctx.resources.framebuffer;
+        let mut game = ctx.resources.game;
+
+        // Reading the data register clears RXNE
+        while let Ok(byte) = serial.read() {
+            if !line.push(byte) {
+                // Echo, terminals don't show what's typed by themselves
+                serial.write_char(byte as char).ok();
+                continue;
+            }
+            serial.write_str("\r\n").ok();
+            let command = match line.line().and_then(Command::parse) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(error) => {
+                    write!(serial, "error: {}\r\n", error).ok();
+                    continue;
+                }
+            };
+            let before = *settings;
+            // New mazes go through the game, which has its say on when
+            let new_maze = match command {
+                Command::Seed(Some(seed)) => Some(Event::NewMaze { level: None, seed: Some(seed) }),
+                Command::Regen => Some(Event::NewMaze { level: None, seed: None }),
+                Command::Level(Some(level)) => Some(Event::NewMaze { level: Some(level), seed: None }),
+                _ => None,
+            };
+            let request = match new_maze.map(|event| game.lock(|game| game.handle(event))) {
+                Some(Some(Effect::Generate(request))) => Some(request),
+                Some(_) => {
+                    serial.write_str("error: busy, try again\r\n").ok();
+                    continue;
+                }
+                None => None,
+            };
+            if let Some(request) = request {
+                settings.level = request.level;
+                let generator = &mut *ctx.resources.generator;
+                *ctx.resources.carving = Some(start_maze(maze, ball, &mut framebuffer, generator, *ctx.resources.algorithm, &request));
+            }
+            let (maze, ball) = (maze.get(), ball.get());
+            let cell_size = maze.cell_size();
+            match command {
+                Command::Seed(None) => {
+                    write!(serial, "seed {:#018x}\r\n", maze.seed).ok();
+                }
+                Command::Seed(Some(seed)) => {
+                    settings.seed = Some(seed);
+                    write!(serial, "seed {:#018x}\r\n", maze.seed).ok();
+                }
+                Command::Regen => {
+                    write!(serial, "seed {:#018x}\r\n", maze.seed).ok();
+                }
+                Command::Solve => match maze.path(ball.to_point(cell_size), maze.end) {
+                    Some(path) => { write!(serial, "{} steps: {}\r\n", path.len(), path).ok(); }
+                    None => { serial.write_str("no way out\r\n").ok(); }
+                },
+                Command::Dump => {
+                    write!(console::CrLf(&mut *serial), "{}", maze.drawing(Some(ball.to_point(cell_size)))).ok();
+                }
+                Command::Export => {
+                    for byte in maze.to_bytes().iter() {
+                        write!(serial, "{:02x}", byte).ok();
+                    }
+                    serial.write_str("\r\n").ok();
+                }
+                Command::Mute(muted) => {
+                    let sounds = &mut *ctx.resources.sounds;
+                    let muted = muted.unwrap_or(!sounds.muted());
+                    sounds.set_muted(muted);
+                    serial.write_str(if muted { "muted\r\n" } else { "unmuted\r\n" }).ok();
+                }
+                Command::Level(None) => {
+                    write!(serial, "level {}\r\n", settings.level).ok();
+                }
+                Command::Level(Some(level)) => {
+                    write!(serial, "level {}\r\n", level).ok();
+                }
+                Command::Brightness(None) => {
+                    let ambient = dimmer.lock(|dimmer| dimmer.ambient());
+                    write!(serial, "brightness {} of {}, ambient {}\r\n", settings.brightness, MAX_BRIGHTNESS, ambient).ok();
+                }
+                Command::Brightness(Some(level)) => {
+                    settings.brightness = level;
+                    dimmer.lock(|dimmer| dimmer.brightness = level);
+                    write!(serial, "brightness {} of {}\r\n", level, MAX_BRIGHTNESS).ok();
+                }
+                Command::AutoDim(automatic) => {
+                    let automatic = automatic.unwrap_or(!settings.auto_brightness);
+                    settings.auto_brightness = automatic;
+                    dimmer.lock(|dimmer| dimmer.automatic = automatic);
+                    serial.write_str(if automatic { "autodim on\r\n" } else { "autodim off\r\n" }).ok();
+                }
+                Command::AutoFinish(automatic) => {
+                    let automatic = automatic.unwrap_or(!settings.auto_finish);
+                    settings.auto_finish = automatic;
+                    game.lock(|game| game.set_auto_finish(automatic));
+                    serial.write_str(if automatic { "autofinish on\r\n" } else { "autofinish off\r\n" }).ok();
+                }
+                Command::Mode(None) => {
+                    write!(serial, "mode {}\r\n", settings.mode.name()).ok();
+                }
+                Command::Mode(Some(mode)) => {
+                    settings.mode = mode;
+                    game.lock(|game| game.set_mode(mode));
+                    write!(serial, "mode {}\r\n", mode.name()).ok();
+                }
+                Command::Stats => {
+                    let point = ball.to_point(cell_size);
+                    let distance = maze.path(point, maze.end).map(|path| path.len()).unwrap_or(0);
+                    write!(serial, "level {} {}x{} {} seed {:#018x}\r\n",
+                        settings.level, maze.dimensions.width, maze.dimensions.height,
+                        ctx.resources.algorithm.name(), maze.seed).ok();
+                    write!(serial, "ball ({}, {}) end ({}, {}) {} steps away\r\n",
+                        point.x, point.y, maze.end.x, maze.end.y, distance).ok();
+                    let moves = game.lock(|game| game.moves());
+                    let shortest = maze.solve().map(|path| path.len()).unwrap_or(0);
+                    write!(serial, "{} moves, {} on the shortest way\r\n", moves, shortest).ok();
+                }
+                Command::Calibrate => {
+                    let (mid_x, mid_y) = adc.lock(|adc| joystick.lock(|joystick| joystick.calibrate(adc)));
+                    settings.joystick_centre = Some((mid_x, mid_y));
+                    write!(serial, "centre {} {}\r\n", mid_x, mid_y).ok();
+                }
+                Command::Pause => {
+                    let state = game.lock(|game| {
+                        game.handle(Event::Pause);
+                        game.state()
+                    });
+                    serial.write_str(if state == State::Paused { "paused\r\n" } else { "playing\r\n" }).ok();
+                }
+                Command::Help => {
+                    write!(serial, "{}\r\n", console::HELP).ok();
+                }
+            }
+            settings.muted = ctx.resources.sounds.muted();
+            if *settings == before {
+                continue;
+            }
+            if let Err(error) = settings.save(&mut *ctx.resources.store) {
+                write!(serial, "error: can't save settings: {:?}\r\n", error).ok();
+            }
+        }
+    }
+
     // Interrupt handlers used to dispatch software tasks
     extern "C" {
         fn USART2();
     }
 };
 
+/// Begins the maze of `request` with `algorithm`, puts the ball back on its start
+/// and draws them, all walls closed. `carve_maze` then carves it with the returned difficulty.
+fn start_maze<M>(maze: &Shared<maze::Maze>, ball: &Shared<ball::Ball>, framebuffer: &mut M, generator: &mut Generator, algorithm: Algorithm, request: &Generate) -> Difficulty
+where M: Mutex<T = Framebuffer> {
+    let difficulty = Difficulty::for_level(request.level);
+    // unsafe is ok here, only the tasks at the lowest priority change the maze, so they
+    // can't interrupt each other. The display task only reads it, and `input` only moves
+    // the ball while the game is playing, which it isn't while a maze is being generated.
+    let (maze, ball) = unsafe { (maze.get_mut(), ball.get_mut()) };
+    generator.reset(algorithm, request.seed.unwrap_or_else(|| next_seed(maze.seed)));
+    if request.from_end {
+        maze.start = maze.end;
+    }
+    difficulty.start(generator, maze);
+    *ball = ball::Ball::from_point(&(maze.start), maze.cell_size());
+    draw_maze(maze, ball, framebuffer);
+    difficulty
+}
+
+/// Carves up to `steps` passages of the maze begun by `start_maze` and draws them.
+/// Returns true once the maze is done, and drawn again whole.
+fn carve_maze<M>(maze: &Shared<maze::Maze>, ball: &Shared<ball::Ball>, framebuffer: &mut M, generator: &mut Generator, difficulty: Difficulty, steps: u16) -> bool
+where M: Mutex<T = Framebuffer> {
+    // unsafe is ok here, see `start_maze`
+    let maze = unsafe { maze.get_mut() };
+    let ball = ball.get();
+    for _ in 0 .. steps {
+        let end = maze.end;
+        let step = match generator.step(maze) {
+            Some(step) => step,
+            None => {
+                difficulty.finish(maze);
+                draw_maze(maze, ball, framebuffer);
+                return true;
+            }
+        };
+        framebuffer.lock(|framebuffer| {
+            display::draw_step(framebuffer, maze, &step);
+            if maze.end != end {
+                // The exit moved along
+                display::draw_maze_cell(framebuffer, maze, end);
+            }
+            display::draw_ball(framebuffer, ball);
+        });
+    }
+    false
+}
+
+/// Draws the maze and the ball.
+fn draw_maze<M>(maze: &maze::Maze, ball: &ball::Ball, framebuffer: &mut M)
+where M: Mutex<T = Framebuffer> {
+    // A row at a time, so the display task only waits for a short while
+    for y in 0 .. display::SCREEN_HEIGHT {
+        framebuffer.lock(|framebuffer| display::draw_maze_row(framebuffer, maze, y));
+    }
+    // The input task may have moved the ball meanwhile, and had it wiped by a row drawn after
+    framebuffer.lock(|framebuffer| display::draw_ball(framebuffer, ball));
+}
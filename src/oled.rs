@@ -0,0 +1,31 @@
+use core::convert::Infallible;
+use embedded_graphics::drawable::Pixel;
+use embedded_graphics::geometry::Size;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::DrawTarget;
+use ssd1306::interface::DisplayInterface;
+use ssd1306::mode::GraphicsMode;
+
+/// Lets `embedded-graphics` 0.6, which `maze-core` draws with, draw on the OLED.
+///
+/// The `ssd1306` driver only implements the drawing trait of an older
+/// `embedded-graphics`, so this goes through its pixel buffer instead.
+pub struct Oled<'a, DI: DisplayInterface>(pub &'a mut GraphicsMode<DI>);
+
+impl<'a, DI: DisplayInterface> DrawTarget<BinaryColor> for Oled<'a, DI> {
+    type Error = Infallible;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Infallible> {
+        let Pixel(point, colour) = pixel;
+        let (width, height) = self.0.get_dimensions();
+        if point.x >= 0 && point.x < width as i32 && point.y >= 0 && point.y < height as i32 {
+            self.0.set_pixel(point.x as u32, point.y as u32, (colour == BinaryColor::On) as u8);
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        let (width, height) = self.0.get_dimensions();
+        Size::new(width as u32, height as u32)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::hal::stm32;
+use maze_core::dma::{DataLines, RowBuffer};
+use maze_core::framebuffer::WIDTH;
+
+/// PC0 to PC5 carry R1, G1, B1, R2, G2 and B2.
+pub const LINES: DataLines = DataLines { r1: 0, g1: 1, b1: 2, r2: 3, g2: 4, b2: 5 };
+
+/// TIM1 ticks per panel clock, 4 MHz at 48 MHz.
+///
+/// A row takes 128 * 12 ticks, 32 us, which has to fit in the shortest bit
+/// plane that `tick` shows while it goes out.
+const PERIOD: u16 = 12;
+/// The panel clock rises halfway through the period, and falls at its end.
+const RISE: u16 = PERIOD / 2;
+/// When the DMA writes the data, well before the clock edges.
+const WRITE: u16 = 1;
+
+/// Clocks rows out to the panel with TIM1 and DMA1 channel 2, see `maze_core::dma`.
+///
+/// TIM1 runs a burst of one period per column and stops by itself. Channel 3N
+/// drives the panel clock on PB1, and each compare on channel 1 has the DMA
+/// copy the next byte of the row into the low byte of GPIOC's output register.
+pub struct PanelDma {
+    tim1: stm32::TIM1,
+}
+
+impl PanelDma {
+    pub fn new(tim1: stm32::TIM1) -> PanelDma {
+        unsafe {
+            (*stm32::RCC::ptr()).apb2enr.modify(|_, w| w.tim1en().set_bit());
+        }
+        tim1.psc.write(|w| w.psc().bits(0));
+        tim1.arr.write(|w| w.arr().bits(PERIOD - 1));
+        // A burst of a period per column each time the counter is started
+        tim1.rcr.write(|w| unsafe { w.rep().bits(WIDTH as u8 - 1) });
+        tim1.cr1.modify(|_, w| w.opm().set_bit());
+        // Loads the repetition counter
+        tim1.egr.write(|w| w.ug().set_bit());
+
+        // Clock low until RISE, so it idles low between bursts like `HUBPort::next_pixel` leaves it
+        tim1.ccmr2_output().modify(|_, w| w.oc3m().pwm_mode2());
+        tim1.ccr3.write(|w| w.ccr().bits(RISE));
+        tim1.ccer.modify(|_, w| w.cc3ne().set_bit());
+        tim1.bdtr.modify(|_, w| w.moe().set_bit());
+
+        tim1.ccr1.write(|w| w.ccr().bits(WRITE));
+        tim1.dier.modify(|_, w| w.cc1de().set_bit());
+        PanelDma { tim1 }
+    }
+
+    /// Whether a row is still going out.
+    pub fn busy(&self) -> bool {
+        self.tim1.cr1.read().cen().bit_is_set()
+    }
+
+    /// Starts clocking `row` out. It has to stay as it is until `busy` is false.
+    pub fn start(&mut self, row: &RowBuffer) {
+        unsafe {
+            let device = stm32::Peripherals::steal();
+            let channel = &device.DMA1.ch2;
+            channel.cr.modify(|_, w| w.en().clear_bit());
+            channel.ndtr.write(|w| w.ndt().bits(WIDTH as u16));
+            channel.mar.write(|w| w.ma().bits(row.as_ptr() as u32));
+            // Byte writes leave the LEDs on PC8 and PC9 alone
+            channel.par.write(|w| w.pa().bits(&device.GPIOC.odr as *const _ as u32));
+            channel.cr.write(|w| w
+                .minc().set_bit()
+                .dir().set_bit()
+                .msize().bits8()
+                .psize().bits8()
+                .pl().very_high()
+                .en().set_bit()
+            );
+        }
+        self.tim1.cr1.modify(|_, w| w.cen().set_bit());
+    }
+}
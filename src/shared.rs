@@ -0,0 +1,33 @@
+use core::cell::UnsafeCell;
+
+/// A resource that some tasks change while others only read it, without any locking.
+///
+/// RTFM 0.5.1 can't give a resource by `&` to some tasks and through `lock` to others,
+/// and the maze is too big to be copied out under a lock, so the maze and the ball are
+/// shared by `&` as one of these and changed through `get_mut`.
+pub struct Shared<T>(UnsafeCell<T>);
+
+// Every task reads it by `&`, `get_mut` says who may change it
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    pub const fn new(value: T) -> Shared<T> {
+        Shared(UnsafeCell::new(value))
+    }
+
+    pub fn get(&self) -> &T {
+        unsafe { &*self.0.get() }
+    }
+
+    /// Changes the value under the tasks reading it.
+    ///
+    /// # Safety
+    ///
+    /// Only one task may change it, or only tasks at the same priority, which can't
+    /// interrupt each other. The tasks reading it have to cope with seeing it half
+    /// changed, and no `&T` from `get` may be held over the change by the task itself.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self) -> &mut T {
+        &mut *self.0.get()
+    }
+}
@@ -98,6 +98,7 @@ static SINE_TABLE: [u16; SINE_TABLE_LEN] = [
 ];
 pub struct SoundController {
     dac: crate::hal::dac::C1,
+    muted: bool,
 }
 
 impl SoundController {
@@ -105,11 +106,24 @@ impl SoundController {
         dac.enable();
         dac.set_value(4095);
         SoundController {
-            dac
+            dac,
+            muted: false,
         }
     }
 
+    /// While muted, `enable` keeps quiet.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
     pub fn enable(&self, freq: u32) {
+        if self.muted {
+            return;
+        }
         unsafe {
             let _device = stm32::Peripherals::steal();
             _device.DAC.cr.modify(|_, w| w